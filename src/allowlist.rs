@@ -0,0 +1,89 @@
+use crate::github::GitHubClient;
+
+/// Who may sign in, from ALLOWED_USERS, ALLOWED_ORGS and ALLOWED_TEAMS
+/// (comma-separated; teams as "org/team-slug"). Matching any entry is enough;
+/// with all three empty everyone with a GitHub account may sign in.
+pub struct Allowlist {
+    users: Vec<String>,
+    orgs: Vec<String>,
+    teams: Vec<(String, String)>,
+}
+
+fn env_list(name: &str) -> Vec<String> {
+    std::env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+impl Allowlist {
+    pub fn from_env() -> Self {
+        let teams = env_list("ALLOWED_TEAMS")
+            .into_iter()
+            .filter_map(|team| match team.split_once('/') {
+                Some((org, slug)) if !org.is_empty() && !slug.is_empty() => Some((org.to_string(), slug.to_string())),
+                _ => {
+                    println!("Ignoring ALLOWED_TEAMS entry {:?}: expected \"org/team-slug\"", team);
+                    None
+                }
+            })
+            .collect();
+
+        Allowlist {
+            users: env_list("ALLOWED_USERS"),
+            orgs: env_list("ALLOWED_ORGS"),
+            teams,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.users.is_empty() && self.orgs.is_empty() && self.teams.is_empty()
+    }
+
+    /// Membership checks need the `read:org` OAuth scope to see private
+    /// memberships.
+    pub fn checks_membership(&self) -> bool {
+        !self.orgs.is_empty() || !self.teams.is_empty()
+    }
+
+    /// Organizations and teams that grant access, for the rejection page.
+    pub fn groups(&self) -> Vec<String> {
+        self.orgs
+            .iter()
+            .cloned()
+            .chain(self.teams.iter().map(|(org, slug)| format!("{}/{}", org, slug)))
+            .collect()
+    }
+
+    /// Whether `login` may sign in. Membership is looked up with the user's
+    /// own token; lookups that fail (e.g. for lack of `read:org`) count as
+    /// "not a member", and the last failure is returned so it can be shown.
+    pub async fn permits(&self, client: &GitHubClient, login: &str) -> Result<bool, String> {
+        if self.is_open() || self.users.iter().any(|u| u.eq_ignore_ascii_case(login)) {
+            return Ok(true);
+        }
+
+        let mut last_error = None;
+        for org in &self.orgs {
+            match client.is_org_member(org).await.map_err(|e| e.to_string()) {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+        for (org, slug) in &self.teams {
+            match client.is_team_member(org, slug, login).await.map_err(|e| e.to_string()) {
+                Ok(true) => return Ok(true),
+                Ok(false) => {}
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match last_error {
+            Some(e) => Err(e),
+            None => Ok(false),
+        }
+    }
+}
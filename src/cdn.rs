@@ -0,0 +1,106 @@
+use futures_util::stream::{self, StreamExt};
+use reqwest::Client;
+use std::error::Error;
+
+use crate::sanitize::encode_path;
+
+/// Default jsDelivr purge endpoint; override with `JSDELIVR_PURGE_URL` (e.g.
+/// to point at a local stub while testing).
+pub const DEFAULT_PURGE_URL: &str = "https://purge.jsdelivr.net";
+
+// Purge requests in flight at once, so bulk operations don't wait for one
+// round-trip per file without flooding jsDelivr
+const CONCURRENT_PURGES: usize = 8;
+
+pub struct CdnPurger {
+    client: Client,
+    base_url: String,
+}
+
+/// Outcome of purging a set of paths.
+pub struct PurgeReport {
+    pub purged: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+impl PurgeReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+
+    /// One-line status for the dashboard.
+    pub fn summary(&self) -> String {
+        if self.failed.is_empty() {
+            format!("CDN cache purged for {} file(s).", self.purged)
+        } else {
+            let (path, error) = &self.failed[0];
+            format!(
+                "CDN cache purge failed for {} of {} file(s) ({}: {}). jsDelivr may keep serving the old version for up to 7 days.",
+                self.failed.len(),
+                self.failed.len() + self.purged,
+                path,
+                error
+            )
+        }
+    }
+}
+
+impl CdnPurger {
+    pub fn new(client: Client, base_url: String) -> Self {
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn purge_url(&self, cdn_path: &str) -> Result<(), Box<dyn Error>> {
+        let url = format!("{}{}", self.base_url, cdn_path);
+        let resp = self.client.get(&url).send().await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let status = resp.status();
+            let error_text = resp.text().await?;
+            Err(format!("{} {}", status, error_text).into())
+        }
+    }
+
+    // Purge the CDN URLs of one file; returns the path and the first error
+    async fn purge_path(&self, owner: &str, repo: &str, branch: &str, is_default_branch: bool, path: String) -> (String, Option<String>) {
+        let mut cdn_paths = vec![format!("/gh/{}/{}@{}/{}", owner, repo, encode_path(branch), encode_path(&path))];
+        if is_default_branch {
+            cdn_paths.push(format!("/gh/{}/{}/{}", owner, repo, encode_path(&path)));
+        }
+
+        for cdn_path in &cdn_paths {
+            if let Err(e) = self.purge_url(cdn_path).await.map_err(|e| e.to_string()) {
+                return (path, Some(e));
+            }
+        }
+        (path, None)
+    }
+
+    /// Ask jsDelivr to drop its cached copies of `paths` so CDN links serve the
+    /// current content (or a 404 after deletion). Both the branch-pinned URL
+    /// and, for the default branch, the bare URL are purged.
+    pub async fn purge(&self, owner: &str, repo: &str, branch: &str, is_default_branch: bool, paths: &[String]) -> PurgeReport {
+        let results: Vec<(String, Option<String>)> = stream::iter(paths.iter().cloned())
+            .map(|path| self.purge_path(owner, repo, branch, is_default_branch, path))
+            .buffer_unordered(CONCURRENT_PURGES)
+            .collect()
+            .await;
+
+        let mut report = PurgeReport { purged: 0, failed: Vec::new() };
+        for (path, error) in results {
+            match error {
+                None => report.purged += 1,
+                Some(e) => {
+                    println!("jsDelivr purge of '{}' failed: {}", path, e);
+                    report.failed.push((path, e));
+                }
+            }
+        }
+
+        report
+    }
+}
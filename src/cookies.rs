@@ -0,0 +1,73 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::request::Parts,
+    response::{IntoResponseParts, ResponseParts},
+};
+use axum_extra::extract::cookie::{Cookie, PrivateCookieJar, SameSite};
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use crate::AppState;
+
+// The browser's cookies, encrypted and authenticated with the server's cookie
+// key so their values can be neither read nor forged client-side. Cookies
+// set by other means (or under an older key) are simply not visible here.
+#[derive(Clone)]
+pub struct Cookies {
+    jar: PrivateCookieJar,
+    secure: bool,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Cookies {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        Ok(Cookies {
+            jar: PrivateCookieJar::from_headers(&parts.headers, state.cookie_key.clone()),
+            secure: state.secure_cookies,
+        })
+    }
+}
+
+impl IntoResponseParts for Cookies {
+    type Error = Infallible;
+
+    fn into_response_parts(self, res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        self.jar.into_response_parts(res)
+    }
+}
+
+impl Cookies {
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        self.jar.get(name)
+    }
+
+    // Add a cookie with HttpOnly, SameSite=Lax (unless set otherwise) and,
+    // on HTTPS deployments, Secure
+    pub fn add(self, mut cookie: Cookie<'static>) -> Self {
+        cookie.set_http_only(true);
+        if cookie.same_site().is_none() {
+            cookie.set_same_site(SameSite::Lax);
+        }
+        cookie.set_secure(self.secure);
+        Cookies {
+            jar: self.jar.add(cookie),
+            secure: self.secure,
+        }
+    }
+
+    // Remove a cookie; without an explicit path the browser would only drop
+    // one scoped to the current URL, so default to the site-wide path
+    pub fn remove(self, cookie: impl Into<Cookie<'static>>) -> Self {
+        let mut cookie = cookie.into();
+        if cookie.path().is_none() {
+            cookie.set_path("/");
+        }
+        Cookies {
+            jar: self.jar.remove(cookie),
+            secure: self.secure,
+        }
+    }
+}
@@ -1,7 +1,11 @@
+use futures_util::stream::{self, StreamExt};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
 use std::error::Error;
 
+use crate::sanitize::encode_path;
+
 #[derive(Clone)]
 pub struct GitHubClient {
     client: Client,
@@ -12,16 +16,38 @@ pub struct GitHubClient {
 struct UploadRequest {
     message: String,
     content: String, // Base64 encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    // Blob SHA of the file being overwritten; required by GitHub for updates
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha: Option<String>,
 }
 
 #[derive(Serialize)]
 struct CreateRepoRequest {
     name: String,
     description: String,
+    private: bool,
     auto_init: bool,
 }
 
+#[derive(Serialize)]
+struct PagesRequest {
+    source: PagesSource,
+}
+
+#[derive(Serialize)]
+struct PagesSource {
+    branch: String,
+    path: String,
+}
+
 #[derive(Deserialize)]
+struct PagesInfo {
+    html_url: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
 #[allow(dead_code)]
 pub struct FileInfo {
     pub name: String,
@@ -29,8 +55,19 @@ pub struct FileInfo {
     pub sha: String,
     pub size: u64,
     pub download_url: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: String, // "file" or "dir"
+}
+
+impl FileInfo {
+    pub fn is_dir(&self) -> bool {
+        self.kind == "dir"
+    }
 }
 
+/// Placeholder committed to make an otherwise empty folder exist in Git.
+pub const FOLDER_PLACEHOLDER: &str = ".gitkeep";
+
 pub struct UploadResult {
     pub cdn_link: String,
     pub pages_link: Option<String>,
@@ -41,16 +78,243 @@ struct User {
     login: String,
 }
 
+#[derive(Deserialize)]
+struct RepoInfo {
+    default_branch: String,
+    #[serde(default)]
+    private: bool,
+}
+
+#[derive(Deserialize)]
+struct RepoListItem {
+    full_name: String,
+    #[serde(default)]
+    permissions: Option<RepoPermissions>,
+}
+
+#[derive(Deserialize)]
+struct RepoPermissions {
+    push: bool,
+}
+
+#[derive(Deserialize)]
+struct Branch {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct GitRef {
+    object: GitObject,
+}
+
+#[derive(Deserialize)]
+struct GitObject {
+    sha: String,
+}
+
+#[derive(Serialize)]
+struct CreateRefRequest {
+    #[serde(rename = "ref")]
+    ref_name: String,
+    sha: String,
+}
+
+#[derive(Deserialize)]
+pub struct TreeEntry {
+    pub path: String,
+    pub mode: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub sha: String,
+    pub size: Option<u64>,
+}
+
+/// One path to change in a Git Data API commit. A `sha` of `None` deletes the path.
+#[derive(Serialize)]
+pub struct TreeChange {
+    pub path: String,
+    pub mode: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub sha: Option<String>,
+}
+
+impl TreeChange {
+    pub fn put(path: String, mode: String, sha: String) -> Self {
+        Self { path, mode, kind: "blob".to_string(), sha: Some(sha) }
+    }
+
+    pub fn delete(path: String) -> Self {
+        Self { path, mode: "100644".to_string(), kind: "blob".to_string(), sha: None }
+    }
+}
+
+#[derive(Deserialize)]
+struct CommitInfo {
+    tree: GitObject,
+}
+
+#[derive(Serialize)]
+struct CreateTreeRequest<'a> {
+    base_tree: String,
+    tree: &'a [TreeChange],
+}
+
+#[derive(Serialize)]
+struct CreateCommitRequest {
+    message: String,
+    tree: String,
+    parents: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct UpdateRefRequest {
+    sha: String,
+}
+
+/// Entries of a folder listing. `truncated` is set when GitHub cut a
+/// recursive listing short, so some files below the folder are missing.
+#[derive(Default)]
+pub struct FolderListing {
+    pub files: Vec<FileInfo>,
+    pub truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct TreeResponse {
+    tree: Vec<TreeEntry>,
+    #[serde(default)]
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct CommitListItem {
+    sha: String,
+    commit: CommitDetails,
+    #[serde(default)]
+    parents: Vec<GitObject>,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    message: String,
+    committer: CommitSignature,
+}
+
+#[derive(Deserialize)]
+struct CommitSignature {
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct CommitWithFiles {
+    #[serde(default)]
+    files: Vec<CommitFile>,
+}
+
+#[derive(Deserialize)]
+struct CommitFile {
+    filename: String,
+    status: String,
+}
+
+/// Who a token belongs to and, for classic OAuth tokens and PATs, its scopes.
+pub struct TokenInfo {
+    pub login: String,
+    // From X-OAuth-Scopes; None for fine-grained PATs and GitHub App tokens,
+    // which have permissions instead of scopes
+    pub scopes: Option<Vec<String>>,
+}
+
+/// Outcome of probing whether a token may write to a repository.
+pub enum WriteAccess {
+    Allowed,
+    // GitHub's reason, e.g. "Resource not accessible by personal access token"
+    Denied(String),
+    // Missing, or invisible to this token
+    NotFound,
+}
+
+#[derive(Serialize)]
+struct CreateBlobRequest<'a> {
+    content: &'a str,
+    encoding: &'a str,
+}
+
+#[derive(Deserialize)]
+struct Membership {
+    state: String, // "active" or "pending"
+}
+
+#[derive(Deserialize)]
+struct ApiMessage {
+    message: String,
+}
+
+/// A commit from the commits API, newest first.
+pub struct Commit {
+    pub sha: String,
+    pub message: String, // first line only
+    pub date: String,    // RFC 3339, e.g. "2024-05-01T12:00:00Z"
+    pub parent: Option<String>,
+}
+
+/// Files in the trash. `truncated` is set when the retention window had more
+/// commits than are read per view, so older deletes are missing.
+pub struct DeletedFiles {
+    pub files: Vec<DeletedFile>,
+    pub truncated: bool,
+}
+
+/// Most commits read for one trash view
+pub const TRASH_COMMIT_LIMIT: usize = 1000;
+
+/// A file removed by a RustPic delete commit that hasn't been re-created since.
+pub struct DeletedFile {
+    pub path: String,
+    pub deleted_at: String,
+    pub commit_sha: String,
+    // Commit that still contains the file; restore reads the blob from here
+    pub parent_sha: String,
+}
+
+/// Compute the Git blob SHA-1 of `content`, i.e. the same id GitHub reports
+/// as `sha` for a file with these bytes.
+pub fn git_blob_sha(content: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()).as_bytes());
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+// URL of the next page from a `Link` response header, if there is one
+fn next_page(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == "rel=\"next\"")
+            .then(|| url.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+    })
+}
+
 impl GitHubClient {
-    pub fn new(token: String) -> Self {
-        let client = Client::builder()
-            .user_agent("rustpic")
-            .build()
-            .unwrap();
+    /// `client` is the application's shared HTTP client; cloning it is cheap
+    /// and reuses its connection pool.
+    pub fn new(client: Client, token: String) -> Self {
         Self { client, token }
     }
 
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
     pub async fn validate_token(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.token_info().await?.login)
+    }
+
+    pub async fn token_info(&self) -> Result<TokenInfo, Box<dyn Error>> {
         let url = "https://api.github.com/user";
         let resp = self.client
             .get(url)
@@ -59,13 +323,46 @@ impl GitHubClient {
             .await?;
 
         if resp.status().is_success() {
+            let scopes = resp.headers()
+                .get("x-oauth-scopes")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect());
             let user: User = resp.json().await?;
-            Ok(user.login)
+            Ok(TokenInfo { login: user.login, scopes })
         } else {
             Err("Invalid token".into())
         }
     }
 
+    /// Check that the token can write to `owner/repo` by creating an empty
+    /// blob. The blob isn't referenced by any commit, so the repository is
+    /// left unchanged and GitHub garbage-collects it.
+    pub async fn probe_write_access(&self, owner: &str, repo: &str) -> Result<WriteAccess, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/git/blobs", owner, repo);
+        let resp = self.client
+            .post(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&CreateBlobRequest { content: "", encoding: "utf-8" })
+            .send()
+            .await?;
+
+        match resp.status() {
+            status if status.is_success() => Ok(WriteAccess::Allowed),
+            reqwest::StatusCode::NOT_FOUND => Ok(WriteAccess::NotFound),
+            // Empty repositories reject Git Data API calls, so nothing can be
+            // learned there; the first upload goes through the contents API
+            reqwest::StatusCode::CONFLICT => Ok(WriteAccess::Allowed),
+            reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::UNAUTHORIZED => {
+                let message = resp.json::<ApiMessage>().await.map(|m| m.message).unwrap_or_default();
+                Ok(WriteAccess::Denied(message))
+            }
+            status => {
+                let error_text = resp.text().await?;
+                Err(format!("Write access check failed ({}): {}", status, error_text).into())
+            }
+        }
+    }
+
     #[allow(dead_code)]
     pub async fn upload_file(
         &self,
@@ -74,11 +371,13 @@ impl GitHubClient {
         path: &str,
         content_base64: String,
     ) -> Result<String, Box<dyn Error>> {
-        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, path);
+        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, encode_path(path));
         
         let body = UploadRequest {
             message: format!("Upload {} via RustPic", path),
             content: content_base64,
+            branch: None,
+            sha: None,
         };
 
         let resp = self.client
@@ -91,7 +390,7 @@ impl GitHubClient {
         if resp.status().is_success() {
             // Don't try to parse the response body - we don't need it
             // Just construct the CDN link from the path
-            let cdn_link = format!("https://cdn.jsdelivr.net/gh/{}/{}/{}", owner, repo, path);
+            let cdn_link = format!("https://cdn.jsdelivr.net/gh/{}/{}/{}", owner, repo, encode_path(path));
             Ok(cdn_link)
         } else {
             let error_text = resp.text().await?;
@@ -99,6 +398,37 @@ impl GitHubClient {
         }
     }
 
+    /// Whether the token's user is an active member of `org`. Private
+    /// memberships are only visible with the `read:org` scope.
+    pub async fn is_org_member(&self, org: &str) -> Result<bool, Box<dyn Error>> {
+        let url = format!("https://api.github.com/user/memberships/orgs/{}", org);
+        self.active_membership(&url).await
+    }
+
+    /// Whether `username` is an active member of the team `org/team_slug`.
+    pub async fn is_team_member(&self, org: &str, team_slug: &str, username: &str) -> Result<bool, Box<dyn Error>> {
+        let url = format!("https://api.github.com/orgs/{}/teams/{}/memberships/{}", org, team_slug, username);
+        self.active_membership(&url).await
+    }
+
+    async fn active_membership(&self, url: &str) -> Result<bool, Box<dyn Error>> {
+        let resp = self.client
+            .get(url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Membership lookup failed: {}", error_text).into());
+        }
+        let membership: Membership = resp.json().await?;
+        Ok(membership.state == "active")
+    }
+
     pub async fn check_repository_exists(&self, owner: &str, repo: &str) -> Result<bool, Box<dyn Error>> {
         let url = format!("https://api.github.com/repos/{}/{}", owner, repo);
         let resp = self.client
@@ -110,11 +440,132 @@ impl GitHubClient {
         Ok(resp.status().is_success())
     }
 
-    pub async fn create_repository(&self, name: &str, description: &str) -> Result<(), Box<dyn Error>> {
+    /// Whether the repository is private. raw.githubusercontent.com links
+    /// to its files need authentication and can't be used as image previews.
+    pub async fn is_private(&self, owner: &str, repo: &str) -> Result<bool, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}", owner, repo);
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let info: RepoInfo = resp.json().await?;
+            Ok(info.private)
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Repository lookup failed: {}", error_text).into())
+        }
+    }
+
+    pub async fn get_default_branch(&self, owner: &str, repo: &str) -> Result<String, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}", owner, repo);
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let info: RepoInfo = resp.json().await?;
+            Ok(info.default_branch)
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Repository lookup failed: {}", error_text).into())
+        }
+    }
+
+    /// `owner/name` of every repository the user can push to, including
+    /// collaborator and organization repositories, sorted by name.
+    pub async fn list_push_repos(&self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut repos = Vec::new();
+        // 100 per page; stop after 10 pages to bound the number of requests
+        for page in 1..=10 {
+            let page = page.to_string();
+            let resp = self.client
+                .get("https://api.github.com/user/repos")
+                .header("Authorization", format!("token {}", self.token))
+                .query(&[
+                    ("affiliation", "owner,collaborator,organization_member"),
+                    ("per_page", "100"),
+                    ("page", page.as_str()),
+                ])
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                let error_text = resp.text().await?;
+                return Err(format!("Failed to list repositories: {}", error_text).into());
+            }
+
+            let items: Vec<RepoListItem> = resp.json().await?;
+            let last_page = items.len() < 100;
+            repos.extend(items
+                .into_iter()
+                .filter(|repo| repo.permissions.as_ref().map(|p| p.push).unwrap_or(false))
+                .map(|repo| repo.full_name));
+            if last_page {
+                break;
+            }
+        }
+        repos.sort_by_key(|name| name.to_lowercase());
+        Ok(repos)
+    }
+
+    pub async fn list_branches(&self, owner: &str, repo: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/branches?per_page=100", owner, repo);
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let branches: Vec<Branch> = resp.json().await?;
+            Ok(branches.into_iter().map(|b| b.name).collect())
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Make sure `branch` exists, creating it from the tip of the default branch if not.
+    pub async fn ensure_branch(&self, owner: &str, repo: &str, branch: &str) -> Result<(), Box<dyn Error>> {
+        if self.get_branch_head(owner, repo, branch).await.is_ok() {
+            return Ok(());
+        }
+
+        let default_branch = self.get_default_branch(owner, repo).await?;
+        let base_sha = self.get_branch_head(owner, repo, &default_branch).await?;
+
+        let url = format!("https://api.github.com/repos/{}/{}/git/refs", owner, repo);
+        let body = CreateRefRequest {
+            ref_name: format!("refs/heads/{}", branch),
+            sha: base_sha,
+        };
+        let resp = self.client
+            .post(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&body)
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Branch creation failed: {}", error_text).into())
+        }
+    }
+
+    /// Create a repository under the authenticated user's account, with an
+    /// initial commit so it has a default branch. Returns that branch.
+    pub async fn create_repository(&self, name: &str, description: &str, private: bool) -> Result<String, Box<dyn Error>> {
         let url = "https://api.github.com/user/repos";
         let body = CreateRepoRequest {
             name: name.to_string(),
             description: description.to_string(),
+            private,
             auto_init: true,
         };
 
@@ -126,74 +577,291 @@ impl GitHubClient {
             .await?;
 
         if resp.status().is_success() {
-            Ok(())
+            let repo: RepoInfo = resp.json().await?;
+            Ok(repo.default_branch)
         } else {
             let error_text = resp.text().await?;
             Err(format!("Repo creation failed: {}", error_text).into())
         }
     }
 
-    pub async fn list_images(&self, owner: &str, repo: &str, path: &str) -> Result<Vec<FileInfo>, Box<dyn Error>> {
-        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, path);
+    /// Publish `branch` of a repository with GitHub Pages. Returns the site URL
+    /// when GitHub reports one. Pages that are already enabled count as success.
+    pub async fn enable_pages(&self, owner: &str, repo: &str, branch: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/pages", owner, repo);
+        let body = PagesRequest {
+            source: PagesSource { branch: branch.to_string(), path: "/".to_string() },
+        };
+
         let resp = self.client
-            .get(&url)
+            .post(&url)
             .header("Authorization", format!("token {}", self.token))
+            .header("Accept", "application/vnd.github+json")
+            .json(&body)
             .send()
             .await?;
 
         if resp.status().is_success() {
-            let mut files: Vec<FileInfo> = resp.json().await?;
-            
-            // No filtering - show all files
-            
-            // Sort by timestamp extracted from filename (newest first)
-            // Format: filename_timestamp.ext
-            files.sort_by(|a, b| {
-                fn extract_timestamp(name: &str) -> u128 {
-                    // Find extension
-                    if let Some(dot_pos) = name.rfind('.') {
-                        let name_no_ext = &name[..dot_pos];
-                        // Find last underscore
-                        if let Some(underscore_pos) = name_no_ext.rfind('_') {
-                            if let Ok(ts) = name_no_ext[underscore_pos + 1..].parse::<u128>() {
-                                return ts;
-                            }
-                        }
-                    }
-                    0 // Fallback for files without timestamp
-                }
+            let pages: PagesInfo = resp.json().await?;
+            Ok(pages.html_url)
+        } else if resp.status() == reqwest::StatusCode::CONFLICT {
+            Ok(None)
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Enabling GitHub Pages failed: {}", error_text).into())
+        }
+    }
 
-                let ts_a = extract_timestamp(&a.name);
-                let ts_b = extract_timestamp(&b.name);
+    /// List the direct children of `path` on `branch`, or with `recursive`
+    /// every file below it. Built on the Git Trees API, so unlike the contents
+    /// API it isn't capped at 1000 entries. Only the folder's own tree is
+    /// fetched, so browsing doesn't download the whole repository. Entries
+    /// are returned unsorted.
+    pub async fn list_images(&self, owner: &str, repo: &str, branch: &str, path: &str, recursive: bool) -> Result<FolderListing, Box<dyn Error>> {
+        let tree_sha = match self.folder_tree_sha(owner, repo, branch, path).await? {
+            Some(sha) => sha,
+            None => return Ok(FolderListing::default()),
+        };
+        let tree = self.fetch_tree(owner, repo, &tree_sha, recursive).await?;
+        if tree.truncated {
+            println!("Tree of {}/{} at '{}' is truncated, listing is incomplete", owner, repo, path);
+        }
 
-                if ts_a != 0 && ts_b != 0 {
-                    // Sort descending (newest first)
-                    ts_b.cmp(&ts_a)
-                } else {
-                    // Fallback to name sort
-                    a.name.cmp(&b.name)
+        let prefix = if path.is_empty() { String::new() } else { format!("{}/", path) };
+        let mut files: Vec<FileInfo> = tree.tree
+            .into_iter()
+            .filter(|entry| if recursive { entry.kind == "blob" } else { entry.kind == "blob" || entry.kind == "tree" })
+            .map(|entry| {
+                let full_path = format!("{}{}", prefix, entry.path);
+                let name = full_path.rsplit('/').next().unwrap_or(&full_path).to_string();
+                let is_dir = entry.kind == "tree";
+                let download_url = (!is_dir).then(|| format!(
+                    "https://raw.githubusercontent.com/{}/{}/{}/{}",
+                    owner, repo, encode_path(branch), encode_path(&full_path)
+                ));
+                FileInfo {
+                    name,
+                    path: full_path,
+                    sha: entry.sha,
+                    size: entry.size.unwrap_or(0),
+                    download_url,
+                    kind: if is_dir { "dir" } else { "file" }.to_string(),
                 }
-            });
-            
-            // Take first 100
-            Ok(files.into_iter().take(100).collect())
+            })
+            .collect();
+
+        // Hide folder placeholders
+        files.retain(|f| f.name != FOLDER_PLACEHOLDER);
+
+        Ok(FolderListing { files, truncated: tree.truncated })
+    }
+
+    async fn fetch_tree(&self, owner: &str, repo: &str, tree_ish: &str, recursive: bool) -> Result<TreeResponse, Box<dyn Error>> {
+        let mut url = format!("https://api.github.com/repos/{}/{}/git/trees/{}", owner, repo, encode_path(tree_ish));
+        if recursive {
+            url.push_str("?recursive=1");
+        }
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(resp.json().await?)
         } else {
-            Ok(vec![])
+            let error_text = resp.text().await?;
+            Err(format!("Tree lookup failed: {}", error_text).into())
+        }
+    }
+
+    /// Every entry in the repository at `tree_ish`, recursively. Fails when
+    /// GitHub truncates the tree: callers plan commits from it, and acting on
+    /// part of a folder would silently leave the rest behind.
+    pub async fn get_tree(&self, owner: &str, repo: &str, tree_ish: &str) -> Result<Vec<TreeEntry>, Box<dyn Error>> {
+        let tree = self.fetch_tree(owner, repo, tree_ish, true).await?;
+        if tree.truncated {
+            return Err(format!(
+                "{}/{} has too many files for GitHub to list at once, so this can't be done safely",
+                owner, repo
+            ).into());
         }
+        Ok(tree.tree)
     }
 
-    pub async fn delete_file(&self, owner: &str, repo: &str, path: &str, sha: &str) -> Result<(), Box<dyn Error>> {
-        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, path);
+    // Tree SHA of the folder at `path` on `branch`, resolved segment by
+    // segment; None when the folder doesn't exist
+    async fn folder_tree_sha(&self, owner: &str, repo: &str, branch: &str, path: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let mut tree_sha = branch.to_string();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            let tree = self.fetch_tree(owner, repo, &tree_sha, false).await?;
+            tree_sha = match tree.tree.into_iter().find(|e| e.kind == "tree" && e.path == segment) {
+                Some(entry) => entry.sha,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(tree_sha))
+    }
+
+    /// Download the raw bytes of a blob by its SHA.
+    pub async fn get_blob(&self, owner: &str, repo: &str, sha: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/git/blobs/{}", owner, repo, sha);
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .header("Accept", "application/vnd.github.raw")
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(resp.bytes().await?.to_vec())
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Blob download failed: {}", error_text).into())
+        }
+    }
+
+    /// Files whose blob SHA matches `sha` anywhere in the repository, as
+    /// (path, size) pairs.
+    pub async fn find_files_by_sha(&self, owner: &str, repo: &str, branch: &str, sha: &str) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+        let tree = self.get_tree(owner, repo, branch).await?;
+        Ok(tree
+            .into_iter()
+            .filter(|entry| entry.kind == "blob" && entry.sha == sha)
+            .map(|entry| (entry.path, entry.size.unwrap_or(0)))
+            .collect())
+    }
+
+    async fn get_branch_head(&self, owner: &str, repo: &str, branch: &str) -> Result<String, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/git/ref/heads/{}", owner, repo, encode_path(branch));
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            let head: GitRef = resp.json().await?;
+            Ok(head.object.sha)
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Branch lookup failed: {}", error_text).into())
+        }
+    }
+
+    /// Apply several path changes to `branch` as a single commit using the Git
+    /// Data API. Returns the new commit SHA.
+    pub async fn commit_changes(
+        &self,
+        owner: &str,
+        repo: &str,
+        branch: &str,
+        changes: &[TreeChange],
+        message: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        let parent_sha = self.get_branch_head(owner, repo, branch).await?;
+
+        let url = format!("https://api.github.com/repos/{}/{}/git/commits/{}", owner, repo, parent_sha);
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Commit lookup failed: {}", error_text).into());
+        }
+        let parent: CommitInfo = resp.json().await?;
+
+        let url = format!("https://api.github.com/repos/{}/{}/git/trees", owner, repo);
+        let body = CreateTreeRequest { base_tree: parent.tree.sha, tree: changes };
+        let resp = self.client
+            .post(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Tree creation failed: {}", error_text).into());
+        }
+        let tree: GitObject = resp.json().await?;
+
+        let url = format!("https://api.github.com/repos/{}/{}/git/commits", owner, repo);
+        let body = CreateCommitRequest {
+            message: message.to_string(),
+            tree: tree.sha,
+            parents: vec![parent_sha],
+        };
+        let resp = self.client
+            .post(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Commit creation failed: {}", error_text).into());
+        }
+        let commit: GitObject = resp.json().await?;
+
+        let url = format!("https://api.github.com/repos/{}/{}/git/refs/heads/{}", owner, repo, encode_path(branch));
+        let body = UpdateRefRequest { sha: commit.sha.clone() };
+        let resp = self.client
+            .patch(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&body)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Branch update failed: {}", error_text).into());
+        }
+
+        Ok(commit.sha)
+    }
+
+    /// Create an empty folder by committing a placeholder file inside it.
+    pub async fn create_folder(&self, owner: &str, repo: &str, branch: &str, path: &str) -> Result<(), Box<dyn Error>> {
+        let placeholder = format!("{}/{}", path, FOLDER_PLACEHOLDER);
+        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, encode_path(&placeholder));
+
+        let body = UploadRequest {
+            message: format!("Create folder {} via RustPic", path),
+            content: String::new(),
+            branch: Some(branch.to_string()),
+            sha: None,
+        };
+
+        let resp = self.client
+            .put(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&body)
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let error_text = resp.text().await?;
+            Err(format!("Folder creation failed: {}", error_text).into())
+        }
+    }
+
+    pub async fn delete_file(&self, owner: &str, repo: &str, branch: &str, path: &str, sha: &str) -> Result<(), Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, encode_path(path));
         
         #[derive(Serialize)]
         struct DeleteRequest {
             message: String,
             sha: String,
+            branch: String,
         }
 
         let body = DeleteRequest {
             message: format!("Delete {} via RustPic", path),
             sha: sha.to_string(),
+            branch: branch.to_string(),
         };
 
         let resp = self.client
@@ -211,20 +879,35 @@ impl GitHubClient {
         }
     }
 
+    /// Delete several files in a single commit, instead of one contents-API
+    /// commit per file.
+    pub async fn delete_files(&self, owner: &str, repo: &str, branch: &str, paths: &[String]) -> Result<(), Box<dyn Error>> {
+        let changes: Vec<TreeChange> = paths.iter().cloned().map(TreeChange::delete).collect();
+        let message = match paths {
+            [path] => format!("Delete {} via RustPic", path),
+            _ => format!("Delete {} files via RustPic", paths.len()),
+        };
+        self.commit_changes(owner, repo, branch, &changes, &message).await?;
+        Ok(())
+    }
+
     pub async fn upload_file_with_links(
         &self,
         owner: &str,
         repo: &str,
+        branch: &str,
         path: &str,
         content_base64: String,
         file_size_bytes: usize,
     ) -> Result<UploadResult, Box<dyn Error>> {
         // First upload the file
-        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, path);
+        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, encode_path(path));
         
         let body = UploadRequest {
             message: format!("Upload {} via RustPic", path),
             content: content_base64,
+            branch: Some(branch.to_string()),
+            sha: None,
         };
 
         let resp = self.client
@@ -239,22 +922,294 @@ impl GitHubClient {
             return Err(format!("Upload failed: {}", error_text).into());
         }
 
-        // Construct CDN link only if file is under 20MB (jsDelivr limit)
-        let cdn_link = if file_size_bytes <= 20 * 1024 * 1024 {
-            format!("https://cdn.jsdelivr.net/gh/{}/{}/{}", owner, repo, path)
-        } else {
-            // For files >20MB, use GitHub raw URL
-            format!("https://raw.githubusercontent.com/{}/{}/main/{}", owner, repo, path)
+        Ok(self.links_for(owner, repo, branch, path, file_size_bytes as u64).await)
+    }
+
+    /// Overwrite an existing file in place. `sha` is the blob being replaced,
+    /// so a concurrent change makes GitHub reject the update.
+    pub async fn replace_file(
+        &self,
+        owner: &str,
+        repo: &str,
+        branch: &str,
+        path: &str,
+        sha: &str,
+        content_base64: String,
+    ) -> Result<(), Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, encode_path(path));
+
+        let body = UploadRequest {
+            message: format!("Replace {} via RustPic", path),
+            content: content_base64,
+            branch: Some(branch.to_string()),
+            sha: Some(sha.to_string()),
         };
-        
-        // Check if GitHub Pages repo exists (username.github.io)
-        let pages_repo = format!("{}.github.io", owner);
-        let pages_link = if self.check_repository_exists(owner, &pages_repo).await.unwrap_or(false) && repo == pages_repo {
-            Some(format!("https://{}/{}", pages_repo, path))
+
+        let resp = self.client
+            .put(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .json(&body)
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            Ok(())
         } else {
-            None
-        };
+            let error_text = resp.text().await?;
+            Err(format!("Replace failed: {}", error_text).into())
+        }
+    }
+
+    /// Commits on `branch`, newest first, optionally limited to those
+    /// touching `path` and made after `since` (RFC 3339). Pages are followed
+    /// until `limit` commits are found or the history runs out.
+    pub async fn list_commits(
+        &self,
+        owner: &str,
+        repo: &str,
+        branch: &str,
+        path: Option<&str>,
+        since: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<Commit>, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/commits", owner, repo);
+        let per_page = limit.clamp(1, 100).to_string();
+        let mut query = vec![("sha", branch), ("per_page", per_page.as_str())];
+        if let Some(path) = path {
+            query.push(("path", path));
+        }
+        if let Some(since) = since {
+            query.push(("since", since));
+        }
+
+        let mut commits = Vec::new();
+        let mut request = self.client.get(&url).query(&query);
+        loop {
+            let resp = request
+                .header("Authorization", format!("token {}", self.token))
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                let error_text = resp.text().await?;
+                return Err(format!("Failed to list commits: {}", error_text).into());
+            }
+
+            let next = next_page(resp.headers());
+            let items: Vec<CommitListItem> = resp.json().await?;
+            commits.extend(items.into_iter().map(|item| Commit {
+                message: item.commit.message.lines().next().unwrap_or("").to_string(),
+                date: item.commit.committer.date,
+                parent: item.parents.into_iter().next().map(|p| p.sha),
+                sha: item.sha,
+            }));
+            match next {
+                Some(next) if commits.len() < limit => request = self.client.get(next),
+                _ => break,
+            }
+        }
+        commits.truncate(limit);
+        Ok(commits)
+    }
+
+    // Paths removed by commit `sha` relative to its first parent. The commit
+    // API lists files 300 per page and at most 3000 in all; past that the two
+    // trees are compared instead.
+    async fn removed_files(&self, owner: &str, repo: &str, sha: String, parent: String) -> Result<Vec<String>, Box<dyn Error>> {
+        const MAX_LISTED_FILES: usize = 3000;
+
+        let mut removed = Vec::new();
+        let mut listed = 0;
+        let mut url = format!("https://api.github.com/repos/{}/{}/commits/{}", owner, repo, sha);
+        loop {
+            let resp = self.client
+                .get(&url)
+                .header("Authorization", format!("token {}", self.token))
+                .send()
+                .await?;
+
+            if !resp.status().is_success() {
+                let error_text = resp.text().await?;
+                return Err(format!("Commit lookup failed: {}", error_text).into());
+            }
+
+            let next = next_page(resp.headers());
+            let commit: CommitWithFiles = resp.json().await?;
+            listed += commit.files.len();
+            removed.extend(commit.files
+                .into_iter()
+                .filter(|file| file.status == "removed")
+                .map(|file| file.filename));
+            match next {
+                Some(next) => url = next,
+                None => break,
+            }
+        }
+
+        if listed < MAX_LISTED_FILES {
+            return Ok(removed);
+        }
+        let after: std::collections::HashSet<String> = self.get_tree(owner, repo, &sha).await?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        Ok(self.get_tree(owner, repo, &parent).await?
+            .into_iter()
+            .filter(|entry| entry.kind == "blob" && !after.contains(&entry.path))
+            .map(|entry| entry.path)
+            .collect())
+    }
+
+    /// Files deleted through RustPic on `branch` since `since`, most recent
+    /// first. Paths that exist again on the branch are left out.
+    pub async fn list_deleted_files(&self, owner: &str, repo: &str, branch: &str, since: &str) -> Result<DeletedFiles, Box<dyn Error>> {
+        // Every upload is its own commit, so the window can hold many; read
+        // at most TRASH_COMMIT_LIMIT of them. The commits API can't filter by
+        // message, so deletes are picked out here.
+        let commits = self.list_commits(owner, repo, branch, None, Some(since), TRASH_COMMIT_LIMIT).await?;
+        let truncated = commits.len() >= TRASH_COMMIT_LIMIT;
+        let existing: std::collections::HashSet<String> = self.get_tree(owner, repo, branch).await?
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+
+        let deletes: Vec<Commit> = commits
+            .into_iter()
+            .filter(|commit| commit.message.starts_with("Delete ") && commit.message.ends_with(" via RustPic"))
+            .filter(|commit| commit.parent.is_some())
+            .collect();
+        // One lookup per delete commit, a few at a time, kept in order
+        let pairs: Vec<(String, String)> = deletes
+            .iter()
+            .map(|commit| (commit.sha.clone(), commit.parent.clone().unwrap_or_default()))
+            .collect();
+        let removed: Vec<Result<Vec<String>, String>> = stream::iter(pairs)
+            .map(|(sha, parent)| self.removed_files_of(owner, repo, sha, parent))
+            .buffered(8)
+            .collect()
+            .await;
+
+        let mut files: Vec<DeletedFile> = Vec::new();
+        for (commit, paths) in deletes.into_iter().zip(removed) {
+            let parent_sha = commit.parent.unwrap_or_default();
+            for path in paths? {
+                if path.ends_with(FOLDER_PLACEHOLDER)
+                    || existing.contains(&path)
+                    || files.iter().any(|d| d.path == path)
+                {
+                    continue;
+                }
+                files.push(DeletedFile {
+                    path,
+                    deleted_at: commit.date.clone(),
+                    commit_sha: commit.sha.clone(),
+                    parent_sha: parent_sha.clone(),
+                });
+            }
+        }
+        Ok(DeletedFiles { files, truncated })
+    }
+
+    // removed_files with its error as a String, so results can be collected
+    // from a stream
+    async fn removed_files_of(&self, owner: &str, repo: &str, sha: String, parent: String) -> Result<Vec<String>, String> {
+        self.removed_files(owner, repo, sha, parent).await.map_err(|e| e.to_string())
+    }
+
+    /// Blob SHA and size of the file at `path` as of `git_ref` (a branch or
+    /// commit SHA), or `None` if there is no such file.
+    pub async fn file_at(&self, owner: &str, repo: &str, git_ref: &str, path: &str) -> Result<Option<(String, u64)>, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/contents/{}", owner, repo, encode_path(path));
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("token {}", self.token))
+            .query(&[("ref", git_ref)])
+            .send()
+            .await?;
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("File lookup failed: {}", error_text).into());
+        }
+
+        // A directory comes back as an array and doesn't count as a file
+        match resp.json::<FileInfo>().await {
+            Ok(info) if !info.is_dir() => Ok(Some((info.sha, info.size))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Build the public links for a file that already exists on `branch`.
+    pub async fn links_for(&self, owner: &str, repo: &str, branch: &str, path: &str, file_size_bytes: u64) -> UploadResult {
+        let serves_pages = self.serves_pages(owner, repo, branch).await;
+        build_links(owner, repo, branch, path, file_size_bytes, serves_pages)
+    }
+
+    /// Whether `branch` of `repo` is published on GitHub Pages at username.github.io.
+    pub async fn serves_pages(&self, owner: &str, repo: &str, branch: &str) -> bool {
+        // GitHub Pages (username.github.io) only publishes the default branch
+        is_pages_repo(owner, repo)
+            && self.get_default_branch(owner, repo).await.map(|b| b == branch).unwrap_or(false)
+    }
+}
+
+/// Whether `owner/repo` is the `owner.github.io` repository, whose default
+/// branch is served by GitHub Pages.
+pub fn is_pages_repo(owner: &str, repo: &str) -> bool {
+    repo == format!("{}.github.io", owner)
+}
+
+/// Public links for `path`, without any API calls. `serves_pages` comes from
+/// `GitHubClient::serves_pages`.
+pub fn build_links(owner: &str, repo: &str, branch: &str, path: &str, file_size_bytes: u64, serves_pages: bool) -> UploadResult {
+    // Construct CDN link only if file is under 20MB (jsDelivr limit)
+    let cdn_link = if file_size_bytes <= 20 * 1024 * 1024 {
+        format!("https://cdn.jsdelivr.net/gh/{}/{}@{}/{}", owner, repo, encode_path(branch), encode_path(path))
+    } else {
+        // For files >20MB, use GitHub raw URL
+        format!("https://raw.githubusercontent.com/{}/{}/{}/{}", owner, repo, encode_path(branch), encode_path(path))
+    };
+
+    let pages_link = if serves_pages {
+        Some(format!("https://{}.github.io/{}", owner, encode_path(path)))
+    } else {
+        None
+    };
+
+    UploadResult { cdn_link, pages_link }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blob_sha_matches_git() {
+        // `git hash-object /dev/null` and `printf 'hello\n' | git hash-object --stdin`
+        assert_eq!(git_blob_sha(b""), "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391");
+        assert_eq!(git_blob_sha(b"hello\n"), "ce013625030ba8dba906f756967f9e9ca394464a");
+    }
+
+    #[test]
+    fn next_page_follows_link_header() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::LINK,
+            "<https://api.github.com/repositories/1/commits?page=3>; rel=\"last\", \
+             <https://api.github.com/repositories/1/commits?page=2>; rel=\"next\""
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(next_page(&headers).as_deref(), Some("https://api.github.com/repositories/1/commits?page=2"));
 
-        Ok(UploadResult { cdn_link, pages_link })
+        headers.insert(
+            reqwest::header::LINK,
+            "<https://api.github.com/repositories/1/commits?page=1>; rel=\"prev\"".parse().unwrap(),
+        );
+        assert_eq!(next_page(&headers), None);
+        assert_eq!(next_page(&reqwest::header::HeaderMap::new()), None);
     }
 }
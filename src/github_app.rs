@@ -0,0 +1,135 @@
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Mutex;
+
+use crate::github::GitHubClient;
+
+// Installation tokens are refreshed this long before GitHub expires them, so
+// a request never starts with a token that runs out halfway through
+const TOKEN_REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// RustPic installed as a GitHub App. Users sign in through the app's OAuth
+/// client and get user-to-server tokens limited to the repositories the app
+/// is installed on; the app itself authenticates with a JWT signed by its
+/// private key and exchanges it for short-lived installation tokens.
+pub struct GitHubApp {
+    app_id: String,
+    key: EncodingKey,
+    slug: Option<String>,
+    client: Client,
+    // Installation tokens by installation ID, reused until shortly before expiry
+    tokens: Mutex<HashMap<u64, (String, DateTime<Utc>)>>,
+}
+
+#[derive(Serialize)]
+struct Claims {
+    iat: i64,
+    exp: i64,
+    iss: String,
+}
+
+#[derive(Deserialize)]
+struct Installation {
+    id: u64,
+}
+
+#[derive(Deserialize)]
+struct InstallationToken {
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl GitHubApp {
+    /// `private_key_pem` is the PEM file downloaded from the app's settings.
+    pub fn new(client: Client, app_id: String, private_key_pem: &str, slug: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let key = EncodingKey::from_rsa_pem(private_key_pem.as_bytes())?;
+        Ok(Self {
+            app_id,
+            key,
+            slug,
+            client,
+            tokens: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Page where users and organizations pick the repositories the app may access.
+    pub fn install_url(&self) -> Option<String> {
+        self.slug.as_ref().map(|slug| format!("https://github.com/apps/{}/installations/new", slug))
+    }
+
+    /// App JWT, valid for nine minutes (GitHub allows at most ten). Issued a
+    /// minute in the past to tolerate clock drift.
+    fn jwt(&self) -> Result<String, Box<dyn Error>> {
+        let now = Utc::now();
+        let claims = Claims {
+            iat: (now - Duration::seconds(60)).timestamp(),
+            exp: (now + Duration::minutes(9)).timestamp(),
+            iss: self.app_id.clone(),
+        };
+        Ok(jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.key)?)
+    }
+
+    /// Installation of the app that covers `owner/repo`, or None when the app
+    /// isn't installed there.
+    pub async fn installation_id(&self, owner: &str, repo: &str) -> Result<Option<u64>, Box<dyn Error>> {
+        let url = format!("https://api.github.com/repos/{}/{}/installation", owner, repo);
+        let resp = self.client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.jwt()?))
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Failed to look up app installation: {}", error_text).into());
+        }
+        let installation: Installation = resp.json().await?;
+        Ok(Some(installation.id))
+    }
+
+    /// Installation access token, from the cache while it has more than
+    /// TOKEN_REFRESH_MARGIN_MINUTES left, otherwise freshly issued.
+    pub async fn installation_token(&self, installation_id: u64) -> Result<String, Box<dyn Error>> {
+        let fresh_until = Utc::now() + Duration::minutes(TOKEN_REFRESH_MARGIN_MINUTES);
+        if let Some((token, expires_at)) = self.tokens.lock().unwrap().get(&installation_id) {
+            if *expires_at > fresh_until {
+                return Ok(token.clone());
+            }
+        }
+
+        let url = format!("https://api.github.com/app/installations/{}/access_tokens", installation_id);
+        let resp = self.client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.jwt()?))
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?;
+
+        if !resp.status().is_success() {
+            let error_text = resp.text().await?;
+            return Err(format!("Failed to create installation token: {}", error_text).into());
+        }
+        let issued: InstallationToken = resp.json().await?;
+        self.tokens.lock().unwrap().insert(installation_id, (issued.token.clone(), issued.expires_at));
+        Ok(issued.token)
+    }
+
+    /// Client acting as the app on `owner/repo`, for work that shouldn't
+    /// depend on a user's session (such as background scans).
+    pub async fn repo_client(&self, owner: &str, repo: &str) -> Result<Option<GitHubClient>, Box<dyn Error>> {
+        let installation_id = match self.installation_id(owner, repo).await? {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let token = self.installation_token(installation_id).await?;
+        Ok(Some(GitHubClient::new(self.client.clone(), token)))
+    }
+}
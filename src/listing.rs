@@ -0,0 +1,254 @@
+use serde::Deserialize;
+
+use crate::github::FileInfo;
+
+/// Entries per page on the dashboard and the default for the list API.
+pub const DEFAULT_PAGE_SIZE: usize = 60;
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// One page of a listing. `page` is 1-based and clamped to the last page.
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub total_pages: usize,
+}
+
+pub fn paginate<T>(items: Vec<T>, page: usize, per_page: usize) -> Page<T> {
+    let per_page = per_page.clamp(1, MAX_PAGE_SIZE);
+    let total = items.len();
+    let total_pages = total.div_ceil(per_page).max(1);
+    let page = page.clamp(1, total_pages);
+
+    let items = items
+        .into_iter()
+        .skip((page - 1) * per_page)
+        .take(per_page)
+        .collect();
+
+    Page { items, page, per_page, total, total_pages }
+}
+
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "webp", "heic", "heif", "bmp", "svg", "avif", "tif", "tiff", "ico",
+];
+const DOCUMENT_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "odt", "ods", "odp", "rtf", "txt", "md", "csv",
+];
+const ARCHIVE_EXTENSIONS: &[&str] = &["zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst"];
+
+/// Search, filter and sort options shared by the dashboard and the list API.
+/// Everything arrives as strings because HTML forms submit empty fields.
+#[derive(Deserialize, Default)]
+pub struct ListFilter {
+    pub q: Option<String>,
+    #[serde(rename = "type")]
+    pub file_type: Option<String>,
+    pub min_size: Option<String>, // KB
+    pub max_size: Option<String>, // KB
+    pub sort: Option<String>,
+    pub order: Option<String>,
+}
+
+impl ListFilter {
+    fn field(value: &Option<String>) -> Option<&str> {
+        value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        Self::field(&self.q)
+    }
+
+    pub fn file_type(&self) -> Option<&str> {
+        Self::field(&self.file_type).filter(|t| matches!(*t, "image" | "document" | "archive" | "other"))
+    }
+
+    // A bound too large to express in bytes is ignored rather than wrapped
+    fn size_bound(value: &Option<String>) -> Option<u64> {
+        Self::field(value).and_then(|v| v.parse::<u64>().ok()).and_then(|kb| kb.checked_mul(1024))
+    }
+
+    pub fn sort_key(&self) -> &str {
+        match Self::field(&self.sort) {
+            Some("name") => "name",
+            Some("size") => "size",
+            _ => "time",
+        }
+    }
+
+    pub fn ascending(&self) -> bool {
+        match Self::field(&self.order) {
+            Some(order) => order == "asc",
+            // Names read naturally A-Z; time and size default to newest/largest first
+            None => self.sort_key() == "name",
+        }
+    }
+
+    /// Whether any narrowing filter is set. Filtered listings search the whole
+    /// subtree rather than just the current folder.
+    pub fn is_search(&self) -> bool {
+        self.query().is_some()
+            || self.file_type().is_some()
+            || Self::size_bound(&self.min_size).is_some()
+            || Self::size_bound(&self.max_size).is_some()
+    }
+
+    pub fn matches(&self, file: &FileInfo) -> bool {
+        if let Some(q) = self.query() {
+            let (name, q) = (file.name.to_lowercase(), q.to_lowercase());
+            let found = if q.contains(['*', '?']) { glob_match(&q, &name) } else { name.contains(&q) };
+            if !found {
+                return false;
+            }
+        }
+        if let Some(file_type) = self.file_type() {
+            if file.is_dir() || file_category(&file.name) != file_type {
+                return false;
+            }
+        }
+        if let Some(min) = Self::size_bound(&self.min_size) {
+            if file.is_dir() || file.size < min {
+                return false;
+            }
+        }
+        if let Some(max) = Self::size_bound(&self.max_size) {
+            if file.is_dir() || file.size > max {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Query string (without leading `&`) that reproduces this filter, for
+    /// pagination links.
+    pub fn to_query_string(&self) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        for (key, value) in [
+            ("q", &self.q),
+            ("type", &self.file_type),
+            ("min_size", &self.min_size),
+            ("max_size", &self.max_size),
+            ("sort", &self.sort),
+            ("order", &self.order),
+        ] {
+            if let Some(value) = Self::field(value) {
+                query.append_pair(key, value);
+            }
+        }
+        query.finish()
+    }
+
+    /// Drop entries that don't match and sort the rest, folders first.
+    pub fn apply(&self, mut entries: Vec<FileInfo>) -> Vec<FileInfo> {
+        entries.retain(|entry| self.matches(entry));
+
+        let ascending = self.ascending();
+        let sort_key = self.sort_key();
+        entries.sort_by(|a, b| {
+            if a.is_dir() != b.is_dir() {
+                // Folders first, regardless of direction
+                return b.is_dir().cmp(&a.is_dir());
+            }
+            let ordering = match sort_key {
+                "name" => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                "size" => a.size.cmp(&b.size),
+                // Files without an upload timestamp count as oldest
+                _ => upload_timestamp(&a.name).cmp(&upload_timestamp(&b.name)),
+            };
+            let ordering = if ascending { ordering } else { ordering.reverse() };
+            ordering.then_with(|| a.name.cmp(&b.name))
+        });
+        entries
+    }
+}
+
+/// Upload time in milliseconds, parsed from RustPic's `name_<timestamp>.ext`
+/// naming scheme.
+pub fn upload_timestamp(name: &str) -> Option<u128> {
+    let stem = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(name);
+    stem.rsplit_once('_').and_then(|(_, ts)| ts.parse().ok())
+}
+
+/// "image", "document", "archive" or "other", by extension.
+pub fn file_category(name: &str) -> &'static str {
+    let ext = name.rsplit_once('.').map(|(_, ext)| ext.to_lowercase()).unwrap_or_default();
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        "image"
+    } else if DOCUMENT_EXTENSIONS.contains(&ext.as_str()) {
+        "document"
+    } else if ARCHIVE_EXTENSIONS.contains(&ext.as_str()) {
+        "archive"
+    } else {
+        "other"
+    }
+}
+
+// Extensions that make up a category of file_category ("other" has none)
+pub fn category_extensions(category: &str) -> &'static [&'static str] {
+    match category {
+        "image" => IMAGE_EXTENSIONS,
+        "document" => DOCUMENT_EXTENSIONS,
+        "archive" => ARCHIVE_EXTENSIONS,
+        _ => &[],
+    }
+}
+
+// Minimal glob: `*` matches any run of characters, `?` exactly one
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("*.png", "photo.png"));
+        assert!(glob_match("*.png", ".png"));
+        assert!(!glob_match("*.png", "photo.png.jpg"));
+        assert!(glob_match("img_??.jpg", "img_01.jpg"));
+        assert!(!glob_match("img_??.jpg", "img_1.jpg"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("*a*b*", "xxbxxaxx"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "a"));
+    }
+
+    #[test]
+    fn size_bounds_are_in_kilobytes_and_never_overflow() {
+        let bound = |kb: &str| ListFilter::size_bound(&Some(kb.to_string()));
+        assert_eq!(bound("2"), Some(2048));
+        assert_eq!(bound(" "), None);
+        assert_eq!(bound("-1"), None);
+        assert_eq!(bound(&(1u64 << 54).to_string()), None);
+        assert_eq!(bound(&u64::MAX.to_string()), None);
+    }
+
+    #[test]
+    fn glob_handles_multibyte_text() {
+        assert!(glob_match("旅?.png", "旅行.png"));
+        assert!(glob_match("*行*", "旅行照片"));
+    }
+}
@@ -0,0 +1,118 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use rand::RngCore;
+use std::collections::HashMap;
+
+use crate::sanitize::{normalize_repo_path, parse_repo_name};
+
+/// A team member who signs in with a password instead of a GitHub account.
+pub struct LocalUser {
+    password_hash: String,
+    // Folder of the shared repository the user may browse and change; empty
+    // for the whole repository
+    pub folder: String,
+}
+
+/// Single-tenant deployment: the server holds the only GitHub token and
+/// every local user works inside their own folder of one shared repository.
+pub struct SingleTenant {
+    pub token: String,
+    pub owner: String,
+    pub repo: String,
+    users: HashMap<String, LocalUser>,
+    // Hash of a random password, checked for unknown usernames
+    dummy_hash: String,
+}
+
+impl SingleTenant {
+    /// Enabled by SERVER_GITHUB_TOKEN, which then requires SHARED_REPO
+    /// ("owner/name") and LOCAL_USERS_FILE.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let token = match std::env::var("SERVER_GITHUB_TOKEN") {
+            Ok(token) if !token.trim().is_empty() => token.trim().to_string(),
+            _ => return Ok(None),
+        };
+        let shared_repo = std::env::var("SHARED_REPO")
+            .map_err(|_| "SERVER_GITHUB_TOKEN requires SHARED_REPO (owner/name)".to_string())?;
+        let (owner, repo) = parse_repo_name(&shared_repo)
+            .ok_or_else(|| format!("Invalid SHARED_REPO: {}", shared_repo))?;
+        let users_file = std::env::var("LOCAL_USERS_FILE")
+            .map_err(|_| "SERVER_GITHUB_TOKEN requires LOCAL_USERS_FILE".to_string())?;
+        let contents = std::fs::read_to_string(&users_file)
+            .map_err(|e| format!("Failed to read {}: {}", users_file, e))?;
+
+        let mut random = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut random);
+        Ok(Some(SingleTenant {
+            token,
+            owner,
+            repo,
+            users: parse_users(&contents)?,
+            dummy_hash: hash_password(&format!("{:x?}", random))?,
+        }))
+    }
+
+    pub fn user_count(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn user(&self, username: &str) -> Option<&LocalUser> {
+        self.users.get(username)
+    }
+
+    /// Password hash to check a sign-in against. Unknown users get a dummy
+    /// hash so they take as long to reject as a wrong password.
+    pub fn password_hash(&self, username: &str) -> (bool, String) {
+        match self.users.get(username) {
+            Some(user) => (true, user.password_hash.clone()),
+            None => (false, self.dummy_hash.clone()),
+        }
+    }
+}
+
+// One user per line: "username:argon2-hash:folder". Blank lines and lines
+// starting with '#' are ignored.
+fn parse_users(contents: &str) -> Result<HashMap<String, LocalUser>, String> {
+    let mut users = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.splitn(3, ':');
+        let (username, hash, folder) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(u), Some(h), folder) if !u.is_empty() && !h.is_empty() => (u, h, folder.unwrap_or("")),
+            _ => return Err(format!("Line {}: expected username:hash:folder", number + 1)),
+        };
+        if !username.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+            return Err(format!("Line {}: invalid username {:?}", number + 1, username));
+        }
+        PasswordHash::new(hash).map_err(|e| format!("Line {}: invalid password hash: {}", number + 1, e))?;
+        let folder = normalize_repo_path(folder).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+        users.insert(username.to_string(), LocalUser {
+            password_hash: hash.to_string(),
+            folder,
+        });
+    }
+    Ok(users)
+}
+
+/// Argon2id hash in PHC string format, for the users file.
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = SaltString::encode_b64(&salt).map_err(|e| e.to_string())?;
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+/// Deliberately slow; call it off the async runtime.
+pub fn verify_password(hash: &str, password: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
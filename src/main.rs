@@ -5,6 +5,7 @@ use axum::{
     http::{header, StatusCode, Uri},
     extract::DefaultBodyLimit,
 };
+use axum_extra::extract::cookie::Key;
 use tower_http::trace::TraceLayer;
 use std::net::SocketAddr;
 use std::sync::Arc;
@@ -14,14 +15,59 @@ use oauth2::{
     basic::BasicClient,
 };
 
+mod allowlist;
+mod cdn;
+mod cookies;
 mod github;
+mod github_app;
+mod listing;
+mod local_auth;
+mod phash;
 mod routes;
+mod sanitize;
+mod session;
 mod templates;
+mod upload_links;
 
 // Asset version from build time
 // Asset version from build time (generated by build.rs)
 pub const ASSET_VERSION: &str = env!("ASSET_VERSION");
 
+// Shared application state passed to every handler
+pub struct AppState {
+    pub oauth_client: Option<BasicClient>,
+    // Shared-repository mode with local accounts and the server's own token
+    pub single_tenant: Option<local_auth::SingleTenant>,
+    // GitHub accounts allowed to sign in
+    pub allowlist: allowlist::Allowlist,
+    // Set when RustPic runs as a GitHub App instead of a classic OAuth app
+    pub github_app: Option<github_app::GitHubApp>,
+    pub phash_index: Arc<phash::PhashIndex>,
+    // Transliterate non-ASCII file names (e.g. "café" -> "cafe") on upload
+    pub transliterate_filenames: bool,
+    // Shared by every GitHub and CDN request so connections are pooled
+    pub http: reqwest::Client,
+    pub cdn: cdn::CdnPurger,
+    // Deleted files older than this are hidden from the trash view
+    pub trash_retention_days: i64,
+    // Signs the OAuth login state and encrypts all other cookies
+    pub cookie_key: Key,
+    // Server-side sessions; the browser only holds an opaque session ID.
+    // None in stateless mode, where the token lives in an encrypted cookie.
+    pub sessions: Option<session::SessionStore>,
+    pub session_ttl: chrono::Duration,
+    pub identities: session::IdentityCache,
+    pub push_repos: session::RepoListCache,
+    // Mark cookies Secure (HTTPS only)
+    pub secure_cookies: bool,
+    // Origin of shareable links, e.g. "https://pics.example.com"; when
+    // unset it is taken from the request's Host header
+    pub public_url: Option<String>,
+    // Upload-only share links and the credentials they upload with
+    pub upload_links: upload_links::UploadLinkStore,
+}
+
+
 #[derive(RustEmbed)]
 #[folder = "assets"]
 struct Assets;
@@ -65,6 +111,19 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
 async fn main() {
     // Load .env file if present (for local development and deployment)
     dotenv::dotenv().ok();
+
+    // `rustpic hash-password` reads a password from stdin and prints the hash
+    // to put in LOCAL_USERS_FILE
+    if std::env::args().nth(1).as_deref() == Some("hash-password") {
+        let mut password = String::new();
+        std::io::stdin().read_line(&mut password).expect("Failed to read password from stdin");
+        let password = password.trim_end_matches(['\r', '\n']);
+        match local_auth::hash_password(password) {
+            Ok(hash) => println!("{}", hash),
+            Err(e) => eprintln!("Failed to hash password: {}", e),
+        }
+        return;
+    }
     
     // Initialize tracing
     tracing_subscriber::fmt::init();
@@ -103,19 +162,175 @@ async fn main() {
         println!("GitHub OAuth is disabled (set GITHUB_CLIENT_ID and GITHUB_CLIENT_SECRET to enable)");
     }
 
+    let transliterate_filenames = std::env::var("TRANSLITERATE_FILENAMES")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false);
+
+    let purge_url = std::env::var("JSDELIVR_PURGE_URL")
+        .unwrap_or_else(|_| cdn::DEFAULT_PURGE_URL.to_string());
+    println!("jsDelivr purge endpoint: {}", purge_url);
+
+    let trash_retention_days = std::env::var("TRASH_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|days| *days > 0)
+        .unwrap_or(30);
+
+    // A random key works for a single instance but invalidates existing cookies
+    // on restart; set COOKIE_SECRET (64+ bytes) to keep them valid
+    let cookie_key = match std::env::var("COOKIE_SECRET") {
+        Ok(secret) => Key::try_from(secret.as_bytes())
+            .expect("COOKIE_SECRET must be at least 64 bytes long"),
+        Err(_) => {
+            println!("COOKIE_SECRET is not set, using a random cookie signing key");
+            Key::generate()
+        }
+    };
+
+    let session_store = std::env::var("SESSION_STORE").unwrap_or_else(|_| "memory".to_string());
+    let sessions = if session_store == "cookie" {
+        // Every instance must decrypt cookies issued by the others
+        if std::env::var("COOKIE_SECRET").is_err() {
+            panic!("SESSION_STORE=cookie requires COOKIE_SECRET");
+        }
+        println!("Stateless mode: sessions are kept in encrypted cookies");
+        None
+    } else {
+        let backend = session::Backend::parse(&session_store)
+            .expect("SESSION_STORE must be \"memory\", \"file:<path>\" or \"cookie\"");
+        if let session::Backend::File(path) = &backend {
+            println!("Storing sessions in {}", path.display());
+            if std::env::var("COOKIE_SECRET").is_err() {
+                println!("Without COOKIE_SECRET, stored sessions cannot be decrypted after a restart");
+            }
+        }
+        Some(session::SessionStore::new(backend, cookie_key.master()))
+    };
+    // Upload links hold a copy of their creator's credentials, so they live
+    // in their own store that doesn't depend on SESSION_STORE
+    let upload_links_file = std::env::var("UPLOAD_LINKS_FILE").ok().map(std::path::PathBuf::from);
+    if let Some(path) = &upload_links_file {
+        println!("Storing upload links in {}", path.display());
+    }
+    let upload_links = upload_links::UploadLinkStore::new(upload_links_file, cookie_key.master());
+    let session_ttl_hours = std::env::var("SESSION_TTL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|hours| *hours > 0)
+        .unwrap_or(24 * 7);
+
+    // Secure cookies by default when the public URL is HTTPS
+    let secure_cookies = match std::env::var("COOKIE_SECURE") {
+        Ok(v) => v == "1" || v.eq_ignore_ascii_case("true"),
+        Err(_) => std::env::var("OAUTH_CALLBACK_URL")
+            .map(|url| url.starts_with("https://"))
+            .unwrap_or(false),
+    };
+
+    let public_url = std::env::var("PUBLIC_URL")
+        .ok()
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .filter(|url| !url.is_empty());
+    if public_url.is_none() {
+        println!("PUBLIC_URL is not set, upload links will use the request's Host header");
+    }
+
+    // How long a validated token is trusted before asking GitHub again; its
+    // list of repositories is reused for as long
+    let identity_cache_seconds = std::env::var("IDENTITY_CACHE_SECONDS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(300);
+
+    let http = reqwest::Client::builder()
+        .user_agent("rustpic")
+        .build()
+        .expect("Failed to build HTTP client");
+
+    // GitHub App mode: GITHUB_CLIENT_ID/SECRET are then the app's OAuth
+    // credentials and sign-ins yield user-to-server tokens
+    let github_app = std::env::var("GITHUB_APP_ID").ok().map(|app_id| {
+        let private_key = match std::env::var("GITHUB_APP_PRIVATE_KEY") {
+            // Single-line env values often carry the PEM with escaped newlines
+            Ok(pem) => pem.replace("\\n", "\n"),
+            Err(_) => {
+                let path = std::env::var("GITHUB_APP_PRIVATE_KEY_FILE")
+                    .expect("GITHUB_APP_ID requires GITHUB_APP_PRIVATE_KEY or GITHUB_APP_PRIVATE_KEY_FILE");
+                std::fs::read_to_string(&path)
+                    .unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e))
+            }
+        };
+        let slug = std::env::var("GITHUB_APP_SLUG").ok();
+        github_app::GitHubApp::new(http.clone(), app_id, &private_key, slug)
+            .expect("GITHUB_APP_PRIVATE_KEY is not a valid RSA private key")
+    });
+    if github_app.is_some() {
+        println!("GitHub App authentication is enabled");
+    }
+
+    let single_tenant = local_auth::SingleTenant::from_env().unwrap_or_else(|e| panic!("{}", e));
+    if let Some(tenant) = &single_tenant {
+        println!(
+            "Single-tenant mode: {} local user(s) sharing {}/{}",
+            tenant.user_count(), tenant.owner, tenant.repo
+        );
+    }
+
+    let allowlist = allowlist::Allowlist::from_env();
+    if !allowlist.is_open() {
+        println!("Sign-in is restricted by ALLOWED_USERS / ALLOWED_ORGS / ALLOWED_TEAMS");
+    }
+
     let app = Router::new()
         .route("/", get(routes::index))
         .route("/login", post(routes::login))
+        .route("/login/local", post(routes::local_login))
         .route("/auth/github", get(routes::auth_github))
         .route("/auth/callback", get(routes::auth_callback))
         .route("/logout", get(routes::logout))
+        .route("/logout/all", post(routes::logout_all))
+        .route("/accounts/switch", post(routes::switch_account))
         .route("/dashboard", get(routes::dashboard))
+        .route("/setup", get(routes::setup).post(routes::create_storage_repo))
+        .route("/repos/default", post(routes::set_default_repo))
         .route("/upload", post(routes::upload))
         .route("/delete", post(routes::delete_image))
+        .route("/delete/bulk", post(routes::delete_selected))
+        .route("/folder", post(routes::create_folder))
+        .route("/move", post(routes::move_files))
+        .route("/replace", post(routes::replace_file))
+        .route("/api/files", get(routes::api_list_files))
+        .route("/history", get(routes::file_history))
+        .route("/trash", get(routes::trash))
+        .route("/trash/restore", post(routes::restore_file))
+        .route("/duplicates", get(routes::duplicates))
+        .route("/duplicates/scan", post(routes::scan_duplicates))
+        .route("/duplicates/delete", post(routes::delete_duplicates))
+        .route("/links", get(routes::upload_links).post(routes::create_upload_link))
+        .route("/links/revoke", post(routes::revoke_upload_link))
+        .route("/u/:token", get(routes::share_upload_page).post(routes::share_upload))
         .route("/assets/*file", get(static_handler))
         .layer(DefaultBodyLimit::max(50 * 1024 * 1024)) // 50MB limit
         .layer(TraceLayer::new_for_http())
-        .with_state(Arc::new(oauth_client));
+        .with_state(Arc::new(AppState {
+            oauth_client,
+            single_tenant,
+            allowlist,
+            github_app,
+            phash_index: Arc::new(phash::PhashIndex::default()),
+            transliterate_filenames,
+            cdn: cdn::CdnPurger::new(http.clone(), purge_url),
+            http,
+            trash_retention_days,
+            cookie_key,
+            sessions,
+            session_ttl: chrono::Duration::hours(session_ttl_hours),
+            identities: session::IdentityCache::new(std::time::Duration::from_secs(identity_cache_seconds)),
+            push_repos: session::RepoListCache::new(std::time::Duration::from_secs(identity_cache_seconds)),
+            secure_cookies,
+            public_url,
+            upload_links,
+        }));
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3002));
     println!("Listening on http://{}", addr);
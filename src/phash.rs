@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use image::imageops::FilterType;
+
+use crate::github::GitHubClient;
+
+// Raster formats the `image` crate can decode; SVG and HEIC are skipped
+const HASHABLE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff"];
+
+/// Default Hamming distance (out of 64 bits) under which two images are
+/// treated as near-duplicates.
+pub const DEFAULT_THRESHOLD: u32 = 10;
+
+#[derive(Clone)]
+pub struct HashedImage {
+    pub path: String,
+    pub sha: String,
+    pub size: u64,
+    pub width: u32,
+    pub height: u32,
+    pub hash: u64,
+}
+
+#[derive(Clone)]
+pub enum ScanStatus {
+    Running { done: usize, total: usize },
+    Finished,
+    Failed(String),
+}
+
+#[derive(Clone)]
+struct RepoIndex {
+    status: ScanStatus,
+    // Keyed by path; byte-identical copies are separate entries sharing a
+    // blob SHA
+    images: HashMap<String, HashedImage>,
+}
+
+/// In-memory perceptual hash index, one entry per `owner/repo` branch. It is
+/// not persisted, so a restart needs a new scan.
+#[derive(Default)]
+pub struct PhashIndex {
+    repos: Mutex<HashMap<String, RepoIndex>>,
+}
+
+/// Difference hash (dHash): shrink to 9x8 grayscale and record whether each
+/// pixel is brighter than its right-hand neighbour.
+pub fn dhash(content: &[u8]) -> Option<(u64, u32, u32)> {
+    let img = image::load_from_memory(content).ok()?;
+    let (width, height) = (img.width(), img.height());
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Some((hash, width, height))
+}
+
+pub fn is_hashable(path: &str) -> bool {
+    path.rsplit_once('.')
+        .map(|(_, ext)| HASHABLE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+fn repo_key(owner: &str, repo: &str, branch: &str) -> String {
+    format!("{}/{}@{}", owner, repo, branch)
+}
+
+impl PhashIndex {
+    pub fn status(&self, owner: &str, repo: &str, branch: &str) -> Option<ScanStatus> {
+        let repos = self.repos.lock().unwrap();
+        repos.get(&repo_key(owner, repo, branch)).map(|index| index.status.clone())
+    }
+
+    pub fn image_count(&self, owner: &str, repo: &str, branch: &str) -> usize {
+        let repos = self.repos.lock().unwrap();
+        repos.get(&repo_key(owner, repo, branch)).map(|index| index.images.len()).unwrap_or(0)
+    }
+
+    /// Look up an indexed image by its current path.
+    pub fn find(&self, owner: &str, repo: &str, branch: &str, path: &str) -> Option<HashedImage> {
+        let repos = self.repos.lock().unwrap();
+        repos.get(&repo_key(owner, repo, branch))
+            .and_then(|index| index.images.get(path).cloned())
+    }
+
+    /// Drop deleted paths from the index.
+    pub fn remove_paths(&self, owner: &str, repo: &str, branch: &str, paths: &[String]) {
+        let mut repos = self.repos.lock().unwrap();
+        if let Some(index) = repos.get_mut(&repo_key(owner, repo, branch)) {
+            for path in paths {
+                index.images.remove(path);
+            }
+        }
+    }
+
+    /// Follow renamed or moved files. `moves` holds `(from, to)` path pairs.
+    pub fn rename_paths(&self, owner: &str, repo: &str, branch: &str, moves: &[(String, String)]) {
+        let mut repos = self.repos.lock().unwrap();
+        if let Some(index) = repos.get_mut(&repo_key(owner, repo, branch)) {
+            let moved: Vec<(HashedImage, &String)> = moves
+                .iter()
+                .filter_map(|(from, to)| index.images.remove(from).map(|image| (image, to)))
+                .collect();
+            for (mut image, to) in moved {
+                image.path = to.clone();
+                index.images.insert(to.clone(), image);
+            }
+        }
+    }
+
+    /// Group images whose hashes are within `threshold` bits of each other.
+    /// Only groups with two or more members are returned, largest first.
+    pub fn groups(&self, owner: &str, repo: &str, branch: &str, threshold: u32) -> Vec<Vec<HashedImage>> {
+        let images: Vec<HashedImage> = {
+            let repos = self.repos.lock().unwrap();
+            match repos.get(&repo_key(owner, repo, branch)) {
+                Some(index) => index.images.values().cloned().collect(),
+                None => return vec![],
+            }
+        };
+
+        // Union-find over every pair within the threshold
+        let mut parent: Vec<usize> = (0..images.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for i in 0..images.len() {
+            for j in (i + 1)..images.len() {
+                if (images[i].hash ^ images[j].hash).count_ones() <= threshold {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    if a != b {
+                        parent[b] = a;
+                    }
+                }
+            }
+        }
+
+        let mut grouped: HashMap<usize, Vec<HashedImage>> = HashMap::new();
+        for (i, image) in images.into_iter().enumerate() {
+            grouped.entry(root(&mut parent, i)).or_default().push(image);
+        }
+
+        let mut groups: Vec<Vec<HashedImage>> = grouped
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                // Largest resolution first - usually the copy worth keeping
+                group.sort_by(|a, b| (b.width * b.height).cmp(&(a.width * a.height)).then(a.path.cmp(&b.path)));
+                group
+            })
+            .collect();
+        groups.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].path.cmp(&b[0].path)));
+        groups
+    }
+
+    fn set_status(&self, key: &str, status: ScanStatus) {
+        let mut repos = self.repos.lock().unwrap();
+        repos.entry(key.to_string())
+            .or_insert_with(|| RepoIndex { status: ScanStatus::Finished, images: HashMap::new() })
+            .status = status;
+    }
+
+    /// Start a background scan of `branch` of `owner/repo` unless one is
+    /// already running.
+    pub fn spawn_scan(self: &Arc<Self>, client: GitHubClient, owner: String, repo: String, branch: String) {
+        let key = repo_key(&owner, &repo, &branch);
+        {
+            let mut repos = self.repos.lock().unwrap();
+            let index = repos.entry(key.clone())
+                .or_insert_with(|| RepoIndex { status: ScanStatus::Finished, images: HashMap::new() });
+            if let ScanStatus::Running { .. } = index.status {
+                return;
+            }
+            index.status = ScanStatus::Running { done: 0, total: 0 };
+        }
+
+        let index = Arc::clone(self);
+        tokio::spawn(async move {
+            let tree = match client.get_tree(&owner, &repo, &branch).await {
+                Ok(tree) => tree,
+                Err(e) => {
+                    index.set_status(&key, ScanStatus::Failed(e.to_string()));
+                    return;
+                }
+            };
+
+            let candidates: Vec<_> = tree
+                .into_iter()
+                .filter(|entry| entry.kind == "blob" && is_hashable(&entry.path))
+                .collect();
+            let total = candidates.len();
+            println!("Perceptual hash scan of {}: {} images", key, total);
+
+            // Hashes already known by blob SHA, from the previous scan and as
+            // this one goes, so an unchanged or copied blob is hashed once
+            let mut known: HashMap<String, HashedImage> = {
+                let repos = index.repos.lock().unwrap();
+                repos.get(&key)
+                    .map(|existing| existing.images.values().map(|image| (image.sha.clone(), image.clone())).collect())
+                    .unwrap_or_default()
+            };
+
+            let mut images = HashMap::new();
+            for (done, entry) in candidates.into_iter().enumerate() {
+                index.set_status(&key, ScanStatus::Running { done, total });
+
+                if let Some(image) = known.get(&entry.sha) {
+                    let mut image = image.clone();
+                    image.path = entry.path.clone();
+                    images.insert(entry.path, image);
+                    continue;
+                }
+
+                let content = match client.get_blob(&owner, &repo, &entry.sha).await {
+                    Ok(content) => content,
+                    Err(e) => {
+                        println!("Skipping '{}': {}", entry.path, e);
+                        continue;
+                    }
+                };
+                if let Some((hash, width, height)) = dhash(&content) {
+                    let image = HashedImage {
+                        path: entry.path.clone(),
+                        sha: entry.sha.clone(),
+                        size: entry.size.unwrap_or(content.len() as u64),
+                        width,
+                        height,
+                        hash,
+                    };
+                    known.insert(entry.sha, image.clone());
+                    images.insert(entry.path, image);
+                }
+            }
+
+            let mut repos = index.repos.lock().unwrap();
+            repos.insert(key, RepoIndex { status: ScanStatus::Finished, images });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(path: &str, sha: &str, hash: u64) -> HashedImage {
+        HashedImage { path: path.to_string(), sha: sha.to_string(), size: 1, width: 10, height: 10, hash }
+    }
+
+    fn index_of(images: Vec<HashedImage>) -> PhashIndex {
+        let index = PhashIndex::default();
+        index.repos.lock().unwrap().insert(repo_key("o", "r", "main"), RepoIndex {
+            status: ScanStatus::Finished,
+            images: images.into_iter().map(|image| (image.path.clone(), image)).collect(),
+        });
+        index
+    }
+
+    #[test]
+    fn identical_copies_are_grouped() {
+        let index = index_of(vec![image("a.png", "same", 0), image("b/a.png", "same", 0), image("c.png", "other", u64::MAX)]);
+        let groups = index.groups("o", "r", "main", 0);
+        assert_eq!(groups.len(), 1);
+        let paths: Vec<&str> = groups[0].iter().map(|image| image.path.as_str()).collect();
+        assert_eq!(paths, ["a.png", "b/a.png"]);
+        assert!(index.groups("o", "r", "other-branch", 0).is_empty());
+    }
+
+    #[test]
+    fn renames_and_removals_follow_paths() {
+        let index = index_of(vec![image("a.png", "same", 0), image("b.png", "same", 0)]);
+        index.rename_paths("o", "r", "main", &[("a.png".to_string(), "moved/a.png".to_string())]);
+        assert!(index.find("o", "r", "main", "a.png").is_none());
+        assert_eq!(index.find("o", "r", "main", "moved/a.png").unwrap().path, "moved/a.png");
+
+        index.remove_paths("o", "r", "main", &["b.png".to_string()]);
+        assert!(index.find("o", "r", "main", "b.png").is_none());
+        assert_eq!(index.image_count("o", "r", "main"), 1);
+    }
+}
@@ -1,108 +1,709 @@
 use axum::{
-    extract::{Multipart, Query, State},
+    extract::{Multipart, Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::{Html, IntoResponse, Redirect, Response},
-    Form,
+    Form, Json,
 };
-use axum_extra::extract::cookie::{Cookie, CookieJar};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite, SignedCookieJar},
+    Form as ExtraForm,
+};
+use futures_util::stream::{self, StreamExt};
 use serde::Deserialize;
 use base64::{Engine as _, engine::general_purpose};
+use std::collections::HashMap;
 use std::sync::Arc;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
 use oauth2::{
-    basic::BasicClient,
-    AuthorizationCode, CsrfToken, Scope, TokenResponse,
+    AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, RefreshToken, Scope, TokenResponse,
+    basic::BasicTokenResponse,
     reqwest::async_http_client,
 };
 
-use crate::github::GitHubClient;
-use crate::templates::{IndexTemplate, DashboardTemplate};
+use crate::cookies::Cookies;
+use crate::github::{build_links, git_blob_sha, is_pages_repo, DeletedFiles, GitHubClient, TokenInfo, TreeChange, TreeEntry, WriteAccess, FOLDER_PLACEHOLDER};
+use crate::listing::{self, file_category, ListFilter};
+use crate::local_auth;
+use crate::phash::{self, HashedImage, ScanStatus};
+use crate::sanitize::{encode_path, is_valid_branch_name, normalize_repo_path, parse_repo_name, sanitize_filename, sanitize_folder_path};
+use crate::session::{self, Credentials, Session, SESSION_COOKIE, TOKEN_COOKIE};
+use crate::templates::{IndexTemplate, DashboardTemplate, DeniedTemplate, DuplicatesTemplate, SetupTemplate, TrashItem, TrashTemplate, FileVersion, HistoryTemplate, ShareUploadTemplate, UploadLinkRow, UploadLinksTemplate};
+use crate::upload_links::{self, UploadLink};
+use crate::AppState;
+
+// Cookie holding the active session: its ID for the server-side store, or
+// in stateless mode the whole session
+fn active_cookie_name(state: &AppState) -> &'static str {
+    if state.sessions.is_some() { SESSION_COOKIE } else { TOKEN_COOKIE }
+}
+
+// Session behind a value of the active session cookie
+fn session_for(state: &AppState, value: &str) -> Option<Session> {
+    match &state.sessions {
+        Some(store) => store.get(value),
+        None => session::from_cookie_value(value),
+    }
+}
+
+// The signed-in session, from the server-side store or, in stateless mode,
+// from the encrypted token cookie
+fn current_session(state: &AppState, jar: &Cookies) -> Option<Session> {
+    jar.get(active_cookie_name(state)).and_then(|c| session_for(state, c.value()))
+}
+
+// Cookie listing the other accounts signed in on this browser, as values of
+// the active session cookie, so the user can switch between them
+const LINKED_ACCOUNTS_COOKIE: &str = "linked_accounts";
+
+// Accounts that can be linked besides the active one; each is a full session
+// value in stateless mode, so this keeps the cookie well below 4 KB
+const MAX_LINKED_ACCOUNTS: usize = 4;
+
+// Linked accounts that are still signed in, most recently used first
+fn linked_sessions(state: &AppState, jar: &Cookies) -> Vec<(String, Session)> {
+    jar.get(LINKED_ACCOUNTS_COOKIE)
+        .and_then(|c| serde_json::from_str::<Vec<String>>(c.value()).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|value| session_for(state, &value).map(|session| (value, session)))
+        .collect()
+}
+
+fn store_linked(state: &AppState, jar: Cookies, values: Vec<String>) -> Cookies {
+    if values.is_empty() {
+        return jar.remove(Cookie::build(LINKED_ACCOUNTS_COOKIE));
+    }
+    let mut cookie = Cookie::new(LINKED_ACCOUNTS_COOKIE, serde_json::to_string(&values).unwrap_or_default());
+    cookie.set_path("/");
+    cookie.set_max_age(time::Duration::seconds(state.session_ttl.num_seconds()));
+    jar.add(cookie)
+}
+
+// Sign a session out for good
+fn discard_session(state: &AppState, value: &str, session: &Session) {
+    state.identities.forget(&session.credentials.token);
+    state.push_repos.forget(&session.credentials.token);
+    if let Some(store) = &state.sessions {
+        store.remove(value);
+    }
+}
+
+// Sign `username` in on this browser. The account signed in before stays
+// linked so the user can switch back to it; signing in again as an account
+// that is already linked replaces its old session.
+fn sign_in(state: &AppState, jar: Cookies, username: &str, credentials: &Credentials) -> Cookies {
+    let mut previous = linked_sessions(state, &jar);
+    if let Some(active) = jar.get(active_cookie_name(state)) {
+        if let Some(session) = session_for(state, active.value()) {
+            previous.insert(0, (active.value().to_string(), session));
+        }
+    }
+
+    let mut linked = Vec::new();
+    for (value, session) in previous {
+        if session.username == username || linked.len() == MAX_LINKED_ACCOUNTS {
+            discard_session(state, &value, &session);
+        } else {
+            linked.push(value);
+        }
+    }
+    let jar = store_linked(state, jar, linked);
+    jar.add(session_cookie(state, username, credentials))
+}
+
+// After the active account signed out, make the next linked account active,
+// or go back to the login page when there is none
+fn switch_to_next_account(state: &AppState, jar: Cookies) -> Response {
+    let mut linked = linked_sessions(state, &jar);
+    let jar = jar.remove(Cookie::build(SESSION_COOKIE)).remove(Cookie::build(TOKEN_COOKIE));
+    if linked.is_empty() {
+        return (jar.remove(Cookie::build(LINKED_ACCOUNTS_COOKIE)), Redirect::to("/")).into_response();
+    }
+    let (next, _) = linked.remove(0);
+    let jar = store_linked(state, jar, linked.into_iter().map(|(value, _)| value).collect());
+    (jar.add(active_session_cookie(state, next)), Redirect::to("/dashboard")).into_response()
+}
+
+// Resolve the signed-in user's GitHub client and login, or the redirect to
+// send them to when the session is missing, expired or its token was revoked
+async fn authenticate(state: &AppState, jar: &Cookies) -> Result<(GitHubClient, String), Response> {
+    if jar.get(SESSION_COOKIE).is_none() && jar.get(TOKEN_COOKIE).is_none() {
+        return Err(Redirect::to("/").into_response());
+    }
+    let mut session = match current_session(state, jar) {
+        Some(session) => session,
+        None => return Err(Redirect::to("/logout").into_response()),
+    };
+
+    // Local accounts act through the server's token; a user removed from the
+    // users file loses access immediately
+    if let Some(tenant) = &state.single_tenant {
+        if tenant.user(&session.username).is_none() {
+            return Err(Redirect::to("/logout").into_response());
+        }
+        return Ok((GitHubClient::new(state.http.clone(), tenant.token.clone()), session.username));
+    }
+
+    if session.credentials.needs_refresh() {
+        // Refresh tokens are single-use, so the new credentials must be saved;
+        // stateless sessions can't be rewritten here and sign in again instead
+        let (store, session_id) = match (&state.sessions, jar.get(SESSION_COOKIE)) {
+            (Some(store), Some(cookie)) => (store, cookie.value().to_string()),
+            _ => return Err(Redirect::to("/logout").into_response()),
+        };
+        // Only one request refreshes; others waiting here pick up its result
+        let lock = store.refresh_lock(&session_id);
+        let _refreshing = lock.lock().await;
+        session = match store.get(&session_id) {
+            Some(session) => session,
+            None => return Err(Redirect::to("/logout").into_response()),
+        };
+        if session.credentials.needs_refresh() {
+            session.credentials = match refresh_credentials(state, &session.credentials).await {
+                Some(credentials) => credentials,
+                None => return Err(Redirect::to("/logout").into_response()),
+            };
+            store.update(&session_id, &session.credentials);
+        }
+    }
+
+    let token = session.credentials.token;
+    if let Some(username) = state.identities.get(&token) {
+        return Ok((GitHubClient::new(state.http.clone(), token), username));
+    }
+    let client = GitHubClient::new(state.http.clone(), token.clone());
+    let username = match client.validate_token().await.map_err(|e| e.to_string()) {
+        Ok(username) => username,
+        Err(_) => return Err(Redirect::to("/logout").into_response()),
+    };
+    // Membership is re-checked whenever the identity is, so someone removed
+    // from the allowed orgs or teams loses access within the cache TTL
+    if !matches!(state.allowlist.permits(&client, &username).await, Ok(true)) {
+        println!("Signing out {}: no longer on the allowlist", username);
+        return Err(Redirect::to("/logout").into_response());
+    }
+    state.identities.insert(&token, username.clone());
+    Ok((client, username))
+}
+
+// Credentials from an OAuth token response; GitHub App user tokens expire
+// and carry a refresh token
+fn credentials_from(token: &BasicTokenResponse) -> Credentials {
+    Credentials {
+        token: token.access_token().secret().to_string(),
+        refresh_token: token.refresh_token().map(|t| t.secret().to_string()),
+        expires_at: token.expires_in().map(|d| (Utc::now() + Duration::seconds(d.as_secs() as i64)).timestamp()),
+    }
+}
+
+// Exchange a session's refresh token for new credentials
+async fn refresh_credentials(state: &AppState, credentials: &Credentials) -> Option<Credentials> {
+    let client = state.oauth_client.as_ref()?;
+    let refresh_token = RefreshToken::new(credentials.refresh_token.clone()?);
+    match client.exchange_refresh_token(&refresh_token).request_async(async_http_client).await {
+        Ok(token) => Some(credentials_from(&token)),
+        Err(e) => {
+            println!("GitHub token refresh failed: {:?}", e);
+            None
+        }
+    }
+}
+
+// Start a session and return the cookie identifying it
+fn session_cookie(state: &AppState, username: &str, credentials: &Credentials) -> Cookie<'static> {
+    let value = match &state.sessions {
+        Some(store) => store.create(username, credentials, state.session_ttl),
+        None => session::to_cookie_value(username, credentials, state.session_ttl),
+    };
+    active_session_cookie(state, value)
+}
+
+fn active_session_cookie(state: &AppState, value: String) -> Cookie<'static> {
+    let mut cookie = Cookie::new(active_cookie_name(state), value);
+    cookie.set_path("/");
+    cookie.set_max_age(time::Duration::seconds(state.session_ttl.num_seconds()));
+    cookie
+}
+
+// Error message shown once on the next page load
+fn error_cookie(message: impl Into<String>) -> Cookie<'static> {
+    let mut cookie = Cookie::new("upload_error", message.into());
+    cookie.set_path("/");
+    cookie
+}
+
+// Store an error message for the next page load and redirect there
+fn error_redirect(jar: Cookies, message: impl Into<String>, to: &str) -> Response {
+    (jar.add(error_cookie(message)), Redirect::to(to)).into_response()
+}
+
+// Store an operation result (links, notice, purge status) for the dashboard's
+// success box and redirect there
+fn result_redirect(jar: Cookies, result: serde_json::Value, to: &str) -> Response {
+    let mut cookie = Cookie::new("upload_result", result.to_string());
+    cookie.set_path("/");
+    (jar.add(cookie), Redirect::to(to)).into_response()
+}
+
+// Cookie holding a user's default storage repository ("owner/name"); the
+// username is part of the name so accounts sharing a browser don't mix
+fn default_repo_cookie(username: &str) -> String {
+    format!("default_repo_{}", username)
+}
+
+fn remember_default_repo(jar: Cookies, username: &str, full_repo: &str) -> Cookies {
+    let mut cookie = Cookie::new(default_repo_cookie(username), full_repo.to_string());
+    cookie.set_path("/");
+    cookie.set_max_age(time::Duration::days(365));
+    jar.add(cookie)
+}
+
+// Storage repository for a request: the explicitly selected one, else the
+// user's saved default, else their GitHub Pages repository. Local users of a
+// single-tenant deployment always get the shared repository.
+fn storage_repo(state: &AppState, jar: &Cookies, requested: Option<&str>, username: &str) -> (String, String) {
+    if let Some(tenant) = &state.single_tenant {
+        return (tenant.owner.clone(), tenant.repo.clone());
+    }
+    requested
+        .and_then(parse_repo_name)
+        .or_else(|| jar.get(&default_repo_cookie(username)).and_then(|c| parse_repo_name(c.value())))
+        .unwrap_or_else(|| (username.to_string(), format!("{}.github.io", username)))
+}
+
+// Folder a local user is confined to; None when the whole repository is theirs
+fn user_folder<'a>(state: &'a AppState, username: &str) -> Option<&'a str> {
+    state.single_tenant
+        .as_ref()
+        .and_then(|tenant| tenant.user(username))
+        .map(|user| user.folder.as_str())
+        .filter(|folder| !folder.is_empty())
+}
+
+// Whether a normalized repository path is inside the user's folder
+fn path_allowed(state: &AppState, username: &str, path: &str) -> bool {
+    match user_folder(state, username) {
+        Some(folder) => in_folder(folder, path),
+        None => true,
+    }
+}
+
+// `path` is `folder` itself or below it; `alice` doesn't contain `alice2/x`
+fn in_folder(folder: &str, path: &str) -> bool {
+    path == folder || path.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+}
+
+fn outside_folder_error(state: &AppState, username: &str, path: &str) -> String {
+    format!("{} is outside your folder ({}).", if path.is_empty() { "/" } else { path }, user_folder(state, username).unwrap_or(""))
+}
+
+// Dashboard URL that keeps the selected repository, branch and folder
+fn dashboard_url(repo: &str, branch: &str, path: &str) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if !repo.is_empty() {
+        query.append_pair("repo", repo);
+    }
+    if !branch.is_empty() {
+        query.append_pair("branch", branch);
+    }
+    if !path.is_empty() {
+        query.append_pair("path", path);
+    }
+    let query = query.finish();
+    if query.is_empty() {
+        "/dashboard".to_string()
+    } else {
+        format!("/dashboard?{}", query)
+    }
+}
+
+// URL of a per-repository page such as /trash or /duplicates
+fn repo_page_url(page: &str, repo: &str, branch: &str) -> String {
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    if !repo.is_empty() {
+        query.append_pair("repo", repo);
+    }
+    if !branch.is_empty() {
+        query.append_pair("branch", branch);
+    }
+    let query = query.finish();
+    if query.is_empty() {
+        page.to_string()
+    } else {
+        format!("{}?{}", page, query)
+    }
+}
+
+// Breadcrumb trail for a folder path: ("name", "path up to and including name")
+fn breadcrumbs(path: &str) -> Vec<(String, String)> {
+    let mut crumbs = Vec::new();
+    let mut current = String::new();
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        if !current.is_empty() {
+            current.push('/');
+        }
+        current.push_str(segment);
+        crumbs.push((segment.to_string(), current.clone()));
+    }
+    crumbs
+}
+
+// Reasons a freshly signed-in token can't be used to commit files, shown on
+// the login page instead of letting every upload fail later
+async fn permission_problems(state: &AppState, client: &GitHubClient, info: &TokenInfo, owner: &str, repo: &str) -> Vec<String> {
+    // GitHub App user tokens carry the App's permissions rather than classic
+    // scopes, so in that mode only the write probe can tell
+    let scopes = if state.github_app.is_some() { None } else { info.scopes.as_ref() };
+    if let Some(scopes) = scopes {
+        if !scopes.iter().any(|s| s == "repo" || s == "public_repo") {
+            let granted = if scopes.is_empty() { "none".to_string() } else { scopes.join(", ") };
+            return vec![format!(
+                "The token is missing the \"repo\" scope (granted scopes: {}). Create a token with \"repo\" checked.",
+                granted
+            )];
+        }
+    }
+
+    match client.probe_write_access(owner, repo).await.map_err(|e| e.to_string()) {
+        Ok(WriteAccess::Denied(reason)) => {
+            let hint = match scopes {
+                Some(scopes) if !scopes.iter().any(|s| s == "repo") => {
+                    "The token only has the \"public_repo\" scope; private repositories need \"repo\"."
+                }
+                Some(_) => "Check that your account can push to this repository.",
+                None if state.github_app.is_some() => {
+                    "Check that the app is installed on this repository with \"Contents: Read and write\" permission."
+                }
+                None => "Fine-grained tokens need the \"Contents: Read and write\" permission on this repository.",
+            };
+            vec![format!("The token cannot write to {}/{} ({}). {}", owner, repo, reason, hint)]
+        }
+        // A missing repository is created on the setup page after login
+        Ok(WriteAccess::Allowed) | Ok(WriteAccess::NotFound) => Vec::new(),
+        Err(e) => {
+            println!("Skipping permission check for {}/{}: {}", owner, repo, e);
+            Vec::new()
+        }
+    }
+}
+
+// Rejection page for accounts outside the login allowlist, or None when the
+// account may sign in
+async fn allowlist_rejection(state: &AppState, client: &GitHubClient, login: &str) -> Option<Response> {
+    let lookup_error = match state.allowlist.permits(client, login).await {
+        Ok(true) => return None,
+        Ok(false) => None,
+        Err(e) => Some(e),
+    };
+    println!("Rejected sign-in of {}: not on the allowlist", login);
+    let template = DeniedTemplate {
+        username: login.to_string(),
+        groups: state.allowlist.groups(),
+        lookup_error,
+        version: crate::ASSET_VERSION.to_string(),
+    };
+    Some((StatusCode::FORBIDDEN, Html(template.to_string())).into_response())
+}
+
+// Cookie carrying the permission problems found during an OAuth login to the
+// login page
+const LOGIN_DIAGNOSTICS_COOKIE: &str = "login_diagnostics";
 
 #[derive(Deserialize)]
 pub struct LoginParams {
     token: String,
 }
 
-pub async fn index(jar: CookieJar) -> impl IntoResponse {
-    if jar.get("gh_token").is_some() {
+#[derive(Deserialize)]
+pub struct IndexParams {
+    error: Option<String>,
+    // Show the login page to sign in to another account while signed in
+    #[serde(default)]
+    add_account: bool,
+}
+
+pub async fn index(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<IndexParams>,
+) -> impl IntoResponse {
+    let signed_in = current_session(&state, &jar).is_some();
+    if signed_in && !params.add_account {
         return Redirect::to("/dashboard").into_response();
     }
+    // Errors from the OAuth flow arrive as short codes in the query string
+    let diagnostics: Vec<String> = jar.get(LOGIN_DIAGNOSTICS_COOKIE)
+        .and_then(|c| serde_json::from_str(c.value()).ok())
+        .unwrap_or_default();
+    let jar = jar.remove(Cookie::build(LOGIN_DIAGNOSTICS_COOKIE));
+    let error = params.error.map(|code| match code.as_str() {
+        "oauth_disabled" => "GitHub login is not configured on this server.".to_string(),
+        "oauth_denied" => "GitHub login was cancelled.".to_string(),
+        "oauth_state" => "GitHub login expired or did not start here. Please try again.".to_string(),
+        "oauth_failed" => "GitHub login failed. Please try again.".to_string(),
+        "token_validation_failed" => "GitHub returned a token that could not be used.".to_string(),
+        "missing_permissions" => "You signed in, but GitHub did not grant the access RustPic needs.".to_string(),
+        "local_only" => "This server only accepts its own user accounts.".to_string(),
+        "invalid_credentials" => "Invalid username or password.".to_string(),
+        _ => "Login failed.".to_string(),
+    });
     let template = IndexTemplate { 
-        error: None,
+        error,
+        diagnostics,
+        local_login: state.single_tenant.is_some(),
+        adding_account: signed_in,
         version: crate::ASSET_VERSION.to_string(),
     };
-    Html(template.to_string()).into_response()
+    (jar, Html(template.to_string())).into_response()
 }
 
 pub async fn login(
-    jar: CookieJar,
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
     Form(params): Form<LoginParams>,
 ) -> impl IntoResponse {
-    let client = GitHubClient::new(params.token.clone());
-    match client.validate_token().await {
-        Ok(_) => {
-            let mut cookie = Cookie::new("gh_token", params.token);
-            cookie.set_path("/");
-            cookie.set_http_only(true);
-            // In a real app, set secure, same_site, etc.
-            
-            (jar.add(cookie), Redirect::to("/dashboard")).into_response()
-        }
+    if state.single_tenant.is_some() {
+        return Redirect::to("/?error=local_only").into_response();
+    }
+    let client = GitHubClient::new(state.http.clone(), params.token.clone());
+    let info = match client.token_info().await.map_err(|e| e.to_string()) {
+        Ok(info) => info,
         Err(_) => {
             let template = IndexTemplate {
                 error: Some("Invalid GitHub Token".to_string()),
+                diagnostics: Vec::new(),
+                local_login: false,
+                adding_account: false,
                 version: crate::ASSET_VERSION.to_string(),
             };
-            Html(template.to_string()).into_response()
+            return Html(template.to_string()).into_response();
+        }
+    };
+
+    if let Some(rejection) = allowlist_rejection(&state, &client, &info.login).await {
+        return rejection;
+    }
+
+    let (owner, repo_name) = storage_repo(&state, &jar, None, &info.login);
+    let diagnostics = permission_problems(&state, &client, &info, &owner, &repo_name).await;
+    if !diagnostics.is_empty() {
+        let template = IndexTemplate {
+            error: Some("This token can't be used to store files.".to_string()),
+            diagnostics,
+            local_login: false,
+            adding_account: false,
+            version: crate::ASSET_VERSION.to_string(),
+        };
+        return Html(template.to_string()).into_response();
+    }
+
+    state.identities.insert(&params.token, info.login.clone());
+    let jar = sign_in(&state, jar, &info.login, &Credentials::token(params.token));
+    (jar, Redirect::to("/dashboard")).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct LocalLoginParams {
+    username: String,
+    password: String,
+}
+
+// Sign in with a local account (single-tenant mode)
+pub async fn local_login(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<LocalLoginParams>,
+) -> Response {
+    let tenant = match &state.single_tenant {
+        Some(tenant) => tenant,
+        None => return Redirect::to("/").into_response(),
+    };
+
+    let username = params.username.trim().to_string();
+    let (known, hash) = tenant.password_hash(&username);
+    // Argon2 is slow on purpose; keep it off the async workers
+    let valid = tokio::task::spawn_blocking(move || local_auth::verify_password(&hash, &params.password))
+        .await
+        .unwrap_or(false);
+    if !known || !valid {
+        println!("Failed local sign-in for {:?}", username);
+        return Redirect::to("/?error=invalid_credentials").into_response();
+    }
+
+    // Local sessions carry no GitHub token; requests use the server's
+    let jar = sign_in(&state, jar, &username, &Credentials::token(String::new()));
+    (jar, Redirect::to("/dashboard")).into_response()
+}
+
+// Sign the active account out; another linked account takes its place
+pub async fn logout(jar: Cookies, State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    if let Some(cookie) = jar.get(active_cookie_name(&state)) {
+        if let Some(session) = session_for(&state, cookie.value()) {
+            discard_session(&state, cookie.value(), &session);
         }
     }
+    switch_to_next_account(&state, jar)
+}
+
+// Invalidate every session of the signed-in user, on all browsers
+pub async fn logout_all(jar: Cookies, State(state): State<Arc<AppState>>) -> Response {
+    let (_client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    // Stateless sessions cannot be revoked; only this browser is signed out
+    if let Some(store) = &state.sessions {
+        let removed = store.remove_user(&username);
+        println!("Signed {} out of {} session(s)", username, removed);
+    }
+    // Upload links act with a copy of the user's credentials; revoke them too
+    let revoked = state.upload_links.revoke_all(&username);
+    if revoked > 0 {
+        println!("Revoked {} upload link(s) of {}", revoked, username);
+    }
+    switch_to_next_account(&state, jar)
 }
 
-pub async fn logout(jar: CookieJar) -> impl IntoResponse {
-    (jar.remove(Cookie::build("gh_token")), Redirect::to("/")).into_response()
+#[derive(Deserialize)]
+pub struct SwitchAccountParams {
+    username: String,
+}
+
+// Make a linked account the active one; the previously active account stays
+// linked
+pub async fn switch_account(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<SwitchAccountParams>,
+) -> Response {
+    let mut linked = linked_sessions(&state, &jar);
+    let position = match linked.iter().position(|(_, session)| session.username == params.username) {
+        Some(position) => position,
+        None => return error_redirect(jar, format!("{} is no longer signed in.", params.username), "/dashboard"),
+    };
+    let (target, _) = linked.remove(position);
+
+    let mut values: Vec<String> = linked.into_iter().map(|(value, _)| value).collect();
+    if let Some(active) = jar.get(active_cookie_name(&state)) {
+        if session_for(&state, active.value()).is_some() {
+            values.insert(0, active.value().to_string());
+        }
+    }
+    let jar = store_linked(&state, jar, values);
+    (jar.add(active_session_cookie(&state, target)), Redirect::to("/dashboard")).into_response()
 }
 
-// OAuth callback query parameters
+// OAuth callback query parameters. GitHub sends `error` instead of `code`
+// when the user cancels.
 #[derive(Deserialize)]
 pub struct AuthCallbackParams {
-    code: String,
-    #[allow(dead_code)]
-    state: String, // CSRF token - kept for future validation
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+// CSRF state and PKCE verifier of a login in progress, kept in a signed cookie
+// between the redirect to GitHub and the callback
+const OAUTH_STATE_COOKIE: &str = "oauth_state";
+
+#[derive(serde::Serialize, Deserialize)]
+struct PendingLogin {
+    state: String,
+    verifier: String,
+}
+
+#[derive(Deserialize)]
+pub struct AuthGithubParams {
+    #[serde(default)]
+    add_account: bool,
 }
 
 // GitHub OAuth login - redirect to GitHub
 pub async fn auth_github(
-    State(oauth_client): State<Arc<Option<BasicClient>>>,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<AuthGithubParams>,
 ) -> impl IntoResponse {
-    let client = match oauth_client.as_ref() {
+    let signed = SignedCookieJar::from_headers(&headers, state.cookie_key.clone());
+    if state.single_tenant.is_some() {
+        return Redirect::to("/?error=local_only").into_response();
+    }
+    let client = match state.oauth_client.as_ref() {
         Some(c) => c,
         None => {
             return Redirect::to("/?error=oauth_disabled").into_response();
         }
     };
 
-    let (auth_url, _csrf_token) = client
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let mut request = client
         .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new("repo".to_string()))
-        .url();
+        .set_pkce_challenge(pkce_challenge);
+    // A GitHub App's access comes from its installations, not OAuth scopes
+    if state.github_app.is_none() {
+        request = request.add_scope(Scope::new("repo".to_string()));
+        // Needed to see private organization and team memberships
+        if state.allowlist.checks_membership() {
+            request = request.add_scope(Scope::new("read:org".to_string()));
+        }
+    }
+    // Otherwise GitHub silently reuses the account signed in on github.com
+    if params.add_account {
+        request = request.add_extra_param("prompt", "select_account");
+    }
+    let (auth_url, csrf_token) = request.url();
+
+    let pending = PendingLogin {
+        state: csrf_token.secret().clone(),
+        verifier: pkce_verifier.secret().clone(),
+    };
+    let mut cookie = Cookie::new(OAUTH_STATE_COOKIE, serde_json::to_string(&pending).unwrap_or_default());
+    cookie.set_path("/auth");
+    cookie.set_http_only(true);
+    // Lax so the cookie survives the top-level redirect back from GitHub
+    cookie.set_same_site(SameSite::Lax);
+    cookie.set_secure(state.secure_cookies);
+    cookie.set_max_age(time::Duration::minutes(10));
 
-    Redirect::to(auth_url.as_str()).into_response()
+    (signed.add(cookie), Redirect::to(auth_url.as_str())).into_response()
 }
 
 // OAuth callback - exchange code for token
 pub async fn auth_callback(
     Query(params): Query<AuthCallbackParams>,
-    jar: CookieJar,
-    State(oauth_client): State<Arc<Option<BasicClient>>>,
+    jar: Cookies,
+    headers: HeaderMap,
+    State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    let client = match oauth_client.as_ref() {
+    let signed = SignedCookieJar::from_headers(&headers, state.cookie_key.clone());
+    let client = match state.oauth_client.as_ref() {
         Some(c) => c,
         None => {
             return Redirect::to("/?error=oauth_disabled").into_response();
         }
     };
 
+    // The login can only be completed once, by the browser that started it
+    let pending = signed.get(OAUTH_STATE_COOKIE)
+        .and_then(|cookie| serde_json::from_str::<PendingLogin>(cookie.value()).ok());
+    let signed = signed.remove(Cookie::build(OAUTH_STATE_COOKIE).path("/auth"));
+
+    if params.error.is_some() {
+        return (signed, Redirect::to("/?error=oauth_denied")).into_response();
+    }
+    let (pending, code) = match (pending, params.code) {
+        (Some(pending), Some(code)) if params.state.as_deref() == Some(pending.state.as_str()) => (pending, code),
+        _ => {
+            println!("Rejected OAuth callback with missing or mismatched state");
+            return (signed, Redirect::to("/?error=oauth_state")).into_response();
+        }
+    };
+
     // Exchange code for access token
     let token_result = client
-        .exchange_code(AuthorizationCode::new(params.code))
+        .exchange_code(AuthorizationCode::new(code))
+        .set_pkce_verifier(PkceCodeVerifier::new(pending.verifier))
         .request_async(async_http_client)
         .await;
 
@@ -111,50 +712,119 @@ pub async fn auth_callback(
             let access_token = token.access_token().secret().to_string();
             
             // Validate token by fetching user info
-            let gh_client = GitHubClient::new(access_token.clone());
-            match gh_client.validate_token().await {
-                Ok(_) => {
-                    let mut cookie = Cookie::new("gh_token", access_token);
-                    cookie.set_path("/");
-                    cookie.set_http_only(true);
-                    (jar.add(cookie), Redirect::to("/dashboard")).into_response()
-                }
-                Err(_) => {
-                    Redirect::to("/?error=token_validation_failed").into_response()
-                }
+            let gh_client = GitHubClient::new(state.http.clone(), access_token.clone());
+            let info = match gh_client.token_info().await.map_err(|e| e.to_string()) {
+                Ok(info) => info,
+                Err(_) => return (signed, Redirect::to("/?error=token_validation_failed")).into_response(),
+            };
+
+            if let Some(rejection) = allowlist_rejection(&state, &gh_client, &info.login).await {
+                return (signed, rejection).into_response();
+            }
+
+            let (owner, repo_name) = storage_repo(&state, &jar, None, &info.login);
+            let diagnostics = permission_problems(&state, &gh_client, &info, &owner, &repo_name).await;
+            if !diagnostics.is_empty() {
+                let mut cookie = Cookie::new(LOGIN_DIAGNOSTICS_COOKIE, serde_json::to_string(&diagnostics).unwrap_or_default());
+                cookie.set_path("/");
+                return (signed, jar.add(cookie), Redirect::to("/?error=missing_permissions")).into_response();
             }
+
+            state.identities.insert(&access_token, info.login.clone());
+            let jar = sign_in(&state, jar, &info.login, &credentials_from(&token));
+            (signed, jar, Redirect::to("/dashboard")).into_response()
         }
         Err(e) => {
             println!("OAuth token exchange error: {:?}", e);
-            Redirect::to("/?error=oauth_failed").into_response()
+            (signed, Redirect::to("/?error=oauth_failed")).into_response()
         }
     }
 }
 
-pub async fn dashboard(jar: CookieJar) -> impl IntoResponse {
-    let token = match jar.get("gh_token") {
-        Some(cookie) => cookie.value().to_string(),
-        None => return Redirect::to("/").into_response(),
-    };
+#[derive(Deserialize)]
+pub struct DashboardParams {
+    repo: Option<String>,
+    branch: Option<String>,
+    path: Option<String>,
+    page: Option<usize>,
+}
 
-    let client = GitHubClient::new(token);
-    let username = match client.validate_token().await {
-        Ok(u) => u,
-        Err(_) => return Redirect::to("/logout").into_response(),
+pub async fn dashboard(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DashboardParams>,
+    Query(filter): Query<ListFilter>,
+) -> impl IntoResponse {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
     };
 
-    // Auto-create GitHub Pages repository if it doesn't exist
+    let (owner, repo_name) = storage_repo(&state, &jar, params.repo.as_deref(), &username);
+    let full_repo = format!("{}/{}", owner, repo_name);
     let pages_repo = format!("{}.github.io", username);
-    let repo_exists = client.check_repository_exists(&username, &pages_repo).await.unwrap_or(false);
 
-    if !repo_exists {
-        // Try to create the GitHub Pages repository
-        let _ = client.create_repository(&pages_repo, "GitHub Pages - Image Storage").await;
+    // Repositories the user can switch to; keep the current one even if the
+    // list is unavailable
+    let mut repos = if state.single_tenant.is_some() {
+        Vec::new()
+    } else {
+        // Listing can take several pages, so reuse it for a while
+        match state.push_repos.get(client.token()) {
+            Some(repos) => repos,
+            None => match client.list_push_repos().await {
+                Ok(repos) => {
+                    state.push_repos.insert(client.token(), repos.clone());
+                    repos
+                }
+                Err(_) => Vec::new(),
+            },
+        }
+    };
+    if !repos.contains(&full_repo) {
+        repos.insert(0, full_repo.clone());
+    }
+    let default_repo = jar.get(&default_repo_cookie(&username))
+        .map(|c| c.value().to_string())
+        .unwrap_or_else(|| format!("{}/{}", username, pages_repo));
+
+    // Browse the requested branch, falling back to the repository's default.
+    // A missing repository sends the user to the setup page instead.
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.map_err(|e| e.to_string());
+    let default_branch = match default_branch {
+        Ok(branch) => branch,
+        Err(_) => {
+            // Local users can't create repositories; the listing below reports the problem
+            if state.single_tenant.is_none() && !client.check_repository_exists(&owner, &repo_name).await.unwrap_or(true) {
+                let message = if state.github_app.is_some() {
+                    format!("Repository {} was not found or RustPic is not installed on it. Install the app there or pick another repository.", full_repo)
+                } else {
+                    format!("Repository {} was not found. Create a storage repository or pick another one.", full_repo)
+                };
+                return error_redirect(jar, message, "/setup");
+            }
+            "main".to_string()
+        }
+    };
+    let branch = params.branch
+        .filter(|b| is_valid_branch_name(b))
+        .unwrap_or_else(|| default_branch.clone());
+    let branches = client.list_branches(&owner, &repo_name).await.unwrap_or_default();
+    let home_folder = state.single_tenant.is_some().then(|| user_folder(&state, &username).unwrap_or_default().to_string());
+    let mut current_path = normalize_repo_path(params.path.as_deref().unwrap_or("")).unwrap_or_default();
+    // Local users start in (and can't leave) their own folder
+    if !path_allowed(&state, &username, &current_path) {
+        current_path = user_folder(&state, &username).unwrap_or_default().to_string();
     }
 
     // Read upload result from cookie (if exists)
     let mut uploaded_link = None;
     let mut pages_link = None;
+    let mut duplicate_of = None;
+    let mut notice = None;
+    let mut purge_status = None;
+    let mut purge_ok = true;
+    let mut extra_links = Vec::new();
     let mut error = None;
     let mut new_jar = jar.clone();
 
@@ -162,6 +832,14 @@ pub async fn dashboard(jar: CookieJar) -> impl IntoResponse {
         if let Ok(result) = serde_json::from_str::<serde_json::Value>(result_cookie.value()) {
             uploaded_link = result.get("cdn_link").and_then(|v| v.as_str()).map(|s| s.to_string());
             pages_link = result.get("pages_link").and_then(|v| v.as_str()).map(|s| s.to_string());
+            duplicate_of = result.get("duplicate_of").and_then(|v| v.as_str()).map(|s| s.to_string());
+            notice = result.get("notice").and_then(|v| v.as_str()).map(|s| s.to_string());
+            purge_status = result.get("purge").and_then(|v| v.as_str()).map(|s| s.to_string());
+            purge_ok = result.get("purge_ok").and_then(|v| v.as_bool()).unwrap_or(true);
+            extra_links = result.get("links")
+                .and_then(|v| v.as_array())
+                .map(|links| links.iter().filter_map(|l| l.as_str().map(|s| s.to_string())).collect())
+                .unwrap_or_default();
         }
         // Remove the cookie after reading
         new_jar = new_jar.remove(Cookie::build("upload_result"));
@@ -174,18 +852,46 @@ pub async fn dashboard(jar: CookieJar) -> impl IntoResponse {
     }
 
     // List images from the repository
-    let images = client.list_images(&username, &pages_repo, "").await.unwrap_or_default();
+    // Searching covers the whole subtree; plain browsing shows one folder
+    let searching = filter.is_search();
+    let folder_listing = client.list_images(&owner, &repo_name, &branch, &current_path, searching).await.unwrap_or_default();
+    let incomplete = folder_listing.truncated;
+    let listing = listing::paginate(filter.apply(folder_listing.files), params.page.unwrap_or(1), listing::DEFAULT_PAGE_SIZE);
+    let (folders, images): (Vec<_>, Vec<_>) = listing.items.into_iter().partition(|entry| entry.is_dir());
 
-    // Always provide pages_link since we enforce the Pages repo
-    if pages_link.is_none() {
-        pages_link = Some(format!("https://{}", pages_repo));
-    }
+    // GitHub Pages serves the default branch of the user site only
+    let pages_base = (repo_name == format!("{}.github.io", owner) && branch == default_branch)
+        .then(|| format!("https://{}", repo_name));
 
     let template = DashboardTemplate {
         username,
-        repo: Some(pages_repo),
+        repo: full_repo,
+        repos,
+        default_repo,
+        home_folder,
+        pages_base,
+        branch,
+        default_branch,
+        branches,
+        breadcrumbs: breadcrumbs(&current_path),
+        current_path,
+        folders,
+        page: listing.page,
+        total_pages: listing.total_pages,
+        total_entries: listing.total,
+        searching,
+        incomplete,
+        filter_query: filter.to_query_string(),
+        filter,
         uploaded_link,
         pages_link,
+        duplicate_of,
+        notice,
+        purge_status,
+        purge_ok,
+        server_sessions: state.sessions.is_some(),
+        other_accounts: linked_sessions(&state, &jar).into_iter().map(|(_, session)| session.username).collect(),
+        extra_links,
         images,
         error,
         version: crate::ASSET_VERSION.to_string(),
@@ -194,169 +900,293 @@ pub async fn dashboard(jar: CookieJar) -> impl IntoResponse {
     (new_jar, Html(template.to_string())).into_response()
 }
 
-pub async fn upload(
-    jar: CookieJar,
-    mut multipart: Multipart,
+#[derive(Deserialize)]
+pub struct DefaultRepoParams {
+    repo: String,
+}
+
+// Remember the selected repository as the one the dashboard opens by default
+pub async fn set_default_repo(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<DefaultRepoParams>,
 ) -> Response {
-    // Early validation - create error cookie and redirect if needed
-    let token = match jar.get("gh_token") {
-        Some(cookie) => cookie.value().to_string(),
-        None => {
-            return (jar, Redirect::to("/")).into_response();
-        }
+    let (_client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
     };
+    if state.single_tenant.is_some() {
+        return Redirect::to("/dashboard").into_response();
+    }
 
-    let client = GitHubClient::new(token.clone());
-    let username = match client.validate_token().await {
-        Ok(u) => u,
-        Err(_) => {
-            return (jar, Redirect::to("/logout")).into_response();
-        }
+    let (owner, repo_name) = match parse_repo_name(&params.repo) {
+        Some(repo) => repo,
+        None => return error_redirect(jar, format!("Invalid repository: {}", params.repo), "/dashboard"),
     };
+    let full_repo = format!("{}/{}", owner, repo_name);
+    let jar = remember_default_repo(jar, &username, &full_repo);
+    (jar, Redirect::to(&dashboard_url(&full_repo, "", ""))).into_response()
+}
 
-    let mut repo = None;
-    let mut path_prefix = String::new();
-    let mut file_content = Vec::new();
-    let mut filename = String::new();
+#[derive(Deserialize)]
+pub struct SetupParams {
+    name: Option<String>,
+}
 
-    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
-        let name = field.name().unwrap_or("").to_string();
-        if name == "repo" {
-            repo = Some(field.text().await.unwrap_or_default());
-        } else if name == "path" {
-            path_prefix = field.text().await.unwrap_or_default();
-        } else if name == "file" {
-            filename = field.file_name().unwrap_or("").to_string();
-            
-            // Use streaming/chunked reading for better compatibility
-            let mut buffer = Vec::new();
-            let mut temp_field = field;
-            
-            while let Some(chunk) = temp_field.chunk().await.transpose() {
-                match chunk {
-                    Ok(data) => {
-                        buffer.extend_from_slice(&data);
-                    }
-                    Err(e) => {
-                        println!("Error reading chunk: {}", e);
-                        let mut cookie = Cookie::new("upload_error", format!("Error reading file chunk: {}", e));
-                        cookie.set_path("/");
-                        cookie.set_http_only(true);
-                        return (jar.add(cookie), Redirect::to("/dashboard")).into_response();
-                    }
-                }
-            }
-            
-            file_content = buffer;
-            println!("Read file '{}': {} bytes (via chunks)", filename, file_content.len());
-        }
+// Storage repository setup: name, visibility and GitHub Pages
+pub async fn setup(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SetupParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+    // The shared repository of a single-tenant deployment is set up by its admin
+    if state.single_tenant.is_some() {
+        return Redirect::to("/dashboard").into_response();
     }
 
-    // Validate file content
-    if file_content.is_empty() {
-        let mut cookie = Cookie::new("upload_error", "Failed to read file content. The file may be empty or corrupted.");
-        cookie.set_path("/");
-        cookie.set_http_only(true);
-        return (jar.add(cookie), Redirect::to("/dashboard")).into_response();
+    let mut error = None;
+    let mut new_jar = jar.clone();
+    if let Some(error_cookie) = jar.get("upload_error") {
+        error = Some(error_cookie.value().to_string());
+        new_jar = new_jar.remove(Cookie::build("upload_error"));
     }
 
-    if filename.is_empty() {
-        let mut cookie = Cookie::new("upload_error", "No file selected.");
-        cookie.set_path("/");
-        cookie.set_http_only(true);
-        return (jar.add(cookie), Redirect::to("/dashboard")).into_response();
-    }
+    let pages_repo = format!("{}.github.io", username);
+    let pages_repo_exists = client.check_repository_exists(&username, &pages_repo).await.unwrap_or(false);
+    let name = params.name
+        .unwrap_or_else(|| if pages_repo_exists { "rustpic-storage".to_string() } else { pages_repo.clone() });
 
-    // Log file info for debugging
-    let file_size_mb = file_content.len() as f64 / 1024.0 / 1024.0;
-    println!("Uploading file: {} ({:.2} MB)", filename, file_size_mb);
+    let template = SetupTemplate {
+        username,
+        name,
+        pages_repo,
+        pages_repo_exists,
+        install_url: state.github_app.as_ref().and_then(|app| app.install_url()),
+        error,
+        version: crate::ASSET_VERSION.to_string(),
+    };
 
-    // Warn if file is large
-    if file_size_mb > 50.0 {
-        let mut cookie = Cookie::new("upload_error", "File too large. Maximum size is 50MB.");
-        cookie.set_path("/");
-        cookie.set_http_only(true);
-        return (jar.add(cookie), Redirect::to("/dashboard")).into_response();
-    }
+    (new_jar, Html(template.to_string())).into_response()
+}
 
-    let repo = match repo {
-        Some(r) => r,
-        None => {
-            let pages_repo = format!("{}.github.io", username);
-            // Default to pages repo if not provided
-            pages_repo
-        }
+#[derive(Deserialize)]
+pub struct CreateRepoParams {
+    name: String,
+    #[serde(default)]
+    visibility: String,
+    // Checkbox: present when GitHub Pages should be enabled
+    pages: Option<String>,
+}
+
+pub async fn create_storage_repo(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<CreateRepoParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
     };
+    if state.single_tenant.is_some() {
+        return Redirect::to("/dashboard").into_response();
+    }
 
-    // Auto-prepend username if repo doesn't contain "/"
-    let full_repo = if repo.contains('/') {
-        repo.clone()
-    } else {
-        format!("{}/{}", username, repo)
+    let name = params.name.trim();
+    let retry_url = format!("/setup?{}", url::form_urlencoded::Serializer::new(String::new()).append_pair("name", name).finish());
+    if parse_repo_name(&format!("{}/{}", username, name)).is_none() {
+        return error_redirect(jar, format!("Invalid repository name: {}", name), &retry_url);
+    }
+    let full_repo = format!("{}/{}", username, name);
+    let private = params.visibility == "private";
+
+    let created = client.create_repository(name, "Image storage via RustPic", private).await
+        .map_err(|e| e.to_string());
+    let default_branch = match created {
+        Ok(branch) => branch,
+        Err(e) => return error_redirect(jar, e, &retry_url),
     };
 
-    // Clean up path and add timestamp to avoid collisions
+    let mut notice = format!("Created {} ({})", full_repo, if private { "private" } else { "public" });
+    let mut jar = remember_default_repo(jar, &username, &full_repo);
+    if params.pages.is_some() {
+        let pages = client.enable_pages(&username, name, &default_branch).await.map_err(|e| e.to_string());
+        match pages {
+            Ok(Some(url)) => notice.push_str(&format!(", published at {}", url)),
+            Ok(None) => notice.push_str(", GitHub Pages enabled"),
+            Err(e) => {
+                // The repository is usable without Pages, so report and carry on
+                jar = jar.add(error_cookie(format!("{} was created, but GitHub Pages could not be enabled: {}", full_repo, e)));
+            }
+        }
+    }
+
+    result_redirect(jar, serde_json::json!({ "notice": notice }), &dashboard_url(&full_repo, "", ""))
+}
+
+// Repository path for a new upload of a sanitized file name, with a
+// timestamp before the extension to avoid collisions
+fn timestamped_path(folder: &str, filename: &str) -> String {
     let timestamp = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_millis(); // Use milliseconds for more precision
 
-    // Add timestamp before file extension
     let filename_with_timestamp = if let Some(pos) = filename.rfind('.') {
         format!("{}_{}{}", &filename[..pos], timestamp, &filename[pos..])
     } else {
         format!("{}_{}", filename, timestamp)
     };
 
-    let full_path = if path_prefix.is_empty() {
+    if folder.is_empty() {
         filename_with_timestamp
     } else {
-        format!("{}/{}", path_prefix.trim_end_matches('/'), filename_with_timestamp)
+        format!("{}/{}", folder, filename_with_timestamp)
+    }
+}
+
+pub async fn upload(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Response {
+    // Early validation - create error cookie and redirect if needed
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let mut repo = None;
+    let mut branch = String::new();
+    let mut path_prefix = String::new();
+    let mut file_content = Vec::new();
+    let mut filename = String::new();
+    let mut force_new_copy = false;
+
+    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+        let name = field.name().unwrap_or("").to_string();
+        if name == "repo" {
+            repo = Some(field.text().await.unwrap_or_default());
+        } else if name == "branch" {
+            branch = field.text().await.unwrap_or_default().trim().to_string();
+        } else if name == "force" {
+            force_new_copy = !field.text().await.unwrap_or_default().is_empty();
+        } else if name == "path" {
+            path_prefix = field.text().await.unwrap_or_default();
+        } else if name == "file" {
+            filename = field.file_name().unwrap_or("").to_string();
+            
+            // Use streaming/chunked reading for better compatibility
+            let mut buffer = Vec::new();
+            let mut temp_field = field;
+            
+            while let Some(chunk) = temp_field.chunk().await.transpose() {
+                match chunk {
+                    Ok(data) => {
+                        buffer.extend_from_slice(&data);
+                    }
+                    Err(e) => {
+                        println!("Error reading chunk: {}", e);
+                        return error_redirect(jar, format!("Error reading file chunk: {}", e), "/dashboard");
+                    }
+                }
+            }
+            
+            file_content = buffer;
+            println!("Read file '{}': {} bytes (via chunks)", filename, file_content.len());
+        }
+    }
+
+    let (owner, repo_name) = storage_repo(&state, &jar, repo.as_deref(), &username);
+    let full_repo = format!("{}/{}", owner, repo_name);
+    let (owner, repo_name) = (owner.as_str(), repo_name.as_str());
+    let return_to = dashboard_url(&full_repo, &branch, "");
+
+    // Validate file content
+    if file_content.is_empty() {
+        return error_redirect(jar, "Failed to read file content. The file may be empty or corrupted.", &return_to);
+    }
+
+    if filename.is_empty() {
+        return error_redirect(jar, "No file selected.", &return_to);
+    }
+
+    // Log file info for debugging
+    let file_size_mb = file_content.len() as f64 / 1024.0 / 1024.0;
+    println!("Uploading file: {} ({:.2} MB)", filename, file_size_mb);
+
+    // Warn if file is large
+    if file_size_mb > 50.0 {
+        return error_redirect(jar, "File too large. Maximum size is 50MB.", &return_to);
+    }
+
+    // Slug the name and folder so they are safe in API paths and CDN URLs
+    let filename = sanitize_filename(&filename, state.transliterate_filenames);
+    let path_prefix = match sanitize_folder_path(&path_prefix, state.transliterate_filenames) {
+        Ok(p) => p,
+        Err(e) => return error_redirect(jar, e, &return_to),
     };
+    let return_to = dashboard_url(&full_repo, &branch, &path_prefix);
+    if !path_allowed(&state, &username, &path_prefix) {
+        return error_redirect(jar, outside_folder_error(&state, &username, &path_prefix), &return_to);
+    }
+
+    let full_path = timestamped_path(&path_prefix, &filename);
 
     let content_base64 = general_purpose::STANDARD.encode(&file_content);
 
-    // Parse owner/repo
-    let parts: Vec<&str> = full_repo.split('/').collect();
-    if parts.len() != 2 {
-        let mut cookie = Cookie::new("upload_error", "Invalid repository format. Use user/repo");
-        cookie.set_path("/");
-        cookie.set_http_only(true);
-        return (jar.add(cookie), Redirect::to("/dashboard")).into_response();
+    // Repositories are only created from the setup page
+    if !client.check_repository_exists(owner, repo_name).await.unwrap_or(true) {
+        return error_redirect(jar, format!("Repository {} does not exist. Create it on the setup page first.", full_repo), "/setup");
     }
-    let owner = parts[0];
-    let repo_name = parts[1];
 
-    // Check if repository exists, create if not
-    let repo_exists = client.check_repository_exists(owner, repo_name).await.unwrap_or(false);
-    if !repo_exists {
-        // Try to create the repository
-        let _ = client.create_repository(repo_name, "Image Storage via RustPic").await;
+    // Upload to the chosen branch (default branch if none), creating it on first use
+    let branch = if branch.is_empty() {
+        client.get_default_branch(owner, repo_name).await.unwrap_or_else(|_| "main".to_string())
+    } else {
+        branch
+    };
+    if !is_valid_branch_name(&branch) {
+        return error_redirect(jar, format!("Invalid branch name: {}", branch), &dashboard_url(&full_repo, "", ""));
+    }
+    if let Err(e) = client.ensure_branch(owner, repo_name, &branch).await {
+        return error_redirect(jar, format!("Upload failed: {}", e), &return_to);
     }
 
-    match client.upload_file_with_links(owner, repo_name, &full_path, content_base64, file_content.len()).await {
-        Ok(result) => {
-            // Store upload result in cookie temporarily
-            let result_json = serde_json::json!({
+    // Skip the upload if the exact same bytes are already stored on the branch
+    if !force_new_copy {
+        let blob_sha = git_blob_sha(&file_content);
+        // Local users only count copies inside their own folder; another
+        // user's path must not leak through the duplicate notice
+        let existing = client.find_files_by_sha(owner, repo_name, &branch, &blob_sha).await
+            .unwrap_or_default()
+            .into_iter()
+            .find(|(path, _)| path_allowed(&state, &username, path));
+        if let Some((existing_path, existing_size)) = existing {
+            println!("Duplicate of '{}' detected, skipping upload", existing_path);
+            let result = client.links_for(owner, repo_name, &branch, &existing_path, existing_size).await;
+            return result_redirect(jar, serde_json::json!({
                 "cdn_link": result.cdn_link,
-                "pages_link": result.pages_link
-            }).to_string();
-
-            let mut cookie = Cookie::new("upload_result", result_json);
-            cookie.set_path("/");
-            cookie.set_http_only(true);
+                "pages_link": result.pages_link,
+                "duplicate_of": existing_path
+            }), &return_to);
+        }
+    }
 
+    match client.upload_file_with_links(owner, repo_name, &branch, &full_path, content_base64, file_content.len()).await {
+        Ok(result) => {
             // Redirect to dashboard to prevent form resubmission
-            (jar.add(cookie), Redirect::to("/dashboard")).into_response()
+            result_redirect(jar, serde_json::json!({
+                "cdn_link": result.cdn_link,
+                "pages_link": result.pages_link
+            }), &return_to)
         }
         Err(e) => {
             // Store error in cookie
-            let mut cookie = Cookie::new("upload_error", format!("Upload failed: {}", e));
-            cookie.set_path("/");
-            cookie.set_http_only(true);
-
-            // Redirect to dashboard
-            (jar.add(cookie), Redirect::to("/dashboard")).into_response()
+            error_redirect(jar, format!("Upload failed: {}", e), &return_to)
         }
     }
 }
@@ -364,41 +1194,1484 @@ pub async fn upload(
 #[derive(Deserialize)]
 pub struct DeleteParams {
     repo: String,
+    #[serde(default)]
+    branch: String,
     path: String,
     sha: String,
 }
 
 pub async fn delete_image(
-    jar: CookieJar,
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
     Form(params): Form<DeleteParams>,
 ) -> impl IntoResponse {
-    let token = match jar.get("gh_token") {
-        Some(cookie) => cookie.value().to_string(),
-        None => return Redirect::to("/").into_response(),
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    // GitHub rejects the delete if the user can't push to the repository
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    let (owner, repo_name) = (owner.as_str(), repo_name.as_str());
+
+    let default_branch = client.get_default_branch(owner, repo_name).await.unwrap_or_else(|_| "main".to_string());
+    let branch = if params.branch.is_empty() {
+        default_branch.clone()
+    } else {
+        params.branch.clone()
+    };
+    let folder = params.path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+    let return_to = dashboard_url(&params.repo, &params.branch, folder);
+    let path_ok = normalize_repo_path(&params.path).is_ok_and(|p| !p.is_empty() && path_allowed(&state, &username, &p));
+    if !path_ok {
+        return error_redirect(jar, outside_folder_error(&state, &username, &params.path), &return_to);
+    }
+
+    let result = client.delete_file(owner, repo_name, &branch, &params.path, &params.sha).await
+        .map_err(|e| e.to_string());
+    if let Err(e) = result {
+        return error_redirect(jar, format!("Delete failed: {}", e), &return_to);
+    }
+
+    // Stop jsDelivr from serving the deleted file from cache
+    let purge = state.cdn.purge(owner, repo_name, &branch, branch == default_branch, std::slice::from_ref(&params.path)).await;
+    result_redirect(jar, serde_json::json!({
+        "notice": format!("Deleted {}", params.path),
+        "purge": purge.summary(),
+        "purge_ok": purge.is_success(),
+    }), &return_to)
+}
+
+#[derive(Deserialize)]
+pub struct ListFilesParams {
+    repo: Option<String>,
+    branch: Option<String>,
+    path: Option<String>,
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+// JSON listing of one folder, paginated: GET /api/files?repo=&path=&page=&per_page=
+// Accepts the same q/type/min_size/max_size/sort/order filters as the dashboard
+pub async fn api_list_files(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ListFilesParams>,
+    Query(filter): Query<ListFilter>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(_) => return api_error(StatusCode::UNAUTHORIZED, "Not signed in"),
+    };
+
+    if let Some(repo) = params.repo.as_deref().filter(|r| parse_repo_name(r).is_none()) {
+        return api_error(StatusCode::BAD_REQUEST, &format!("Invalid repository: {}", repo));
+    }
+    let (owner, repo_name) = storage_repo(&state, &jar, params.repo.as_deref(), &username);
+    let branch = match params.branch.filter(|b| !b.is_empty()) {
+        Some(b) if is_valid_branch_name(&b) => b,
+        Some(b) => return api_error(StatusCode::BAD_REQUEST, &format!("Invalid branch name: {}", b)),
+        None => client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string()),
+    };
+    let path = match normalize_repo_path(params.path.as_deref().unwrap_or("")) {
+        Ok(p) => p,
+        Err(e) => return api_error(StatusCode::BAD_REQUEST, &e),
+    };
+    if !path_allowed(&state, &username, &path) {
+        return api_error(StatusCode::FORBIDDEN, &outside_folder_error(&state, &username, &path));
+    }
+
+    let folder_listing = match client.list_images(&owner, &repo_name, &branch, &path, filter.is_search()).await {
+        Ok(folder_listing) => folder_listing,
+        Err(e) => return api_error(StatusCode::BAD_GATEWAY, &e.to_string()),
+    };
+    let complete = !folder_listing.truncated;
+    let listing = listing::paginate(
+        filter.apply(folder_listing.files),
+        params.page.unwrap_or(1),
+        params.per_page.unwrap_or(listing::DEFAULT_PAGE_SIZE),
+    );
+
+    Json(serde_json::json!({
+        "repo": format!("{}/{}", owner, repo_name),
+        "branch": branch,
+        "path": path,
+        "page": listing.page,
+        "per_page": listing.per_page,
+        "total": listing.total,
+        "total_pages": listing.total_pages,
+        "complete": complete,
+        "entries": listing.items,
+    })).into_response()
+}
+
+fn api_error(status: StatusCode, message: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": message }))).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct CreateFolderParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+    #[serde(default)]
+    parent: String,
+    name: String,
+}
+
+pub async fn create_folder(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<CreateFolderParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
     };
 
-    let client = GitHubClient::new(token);
-    let username = match client.validate_token().await {
-        Ok(u) => u,
-        Err(_) => return Redirect::to("/logout").into_response(),
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    let parent = normalize_repo_path(&params.parent).unwrap_or_default();
+    let return_to = dashboard_url(&params.repo, &params.branch, &parent);
+
+    let name = match sanitize_folder_path(&params.name, state.transliterate_filenames) {
+        Ok(name) if !name.is_empty() => name,
+        Ok(_) => return error_redirect(jar, "Folder name is empty.", &return_to),
+        Err(e) => return error_redirect(jar, e, &return_to),
+    };
+    let folder = if parent.is_empty() { name } else { format!("{}/{}", parent, name) };
+    if !path_allowed(&state, &username, &folder) {
+        return error_redirect(jar, outside_folder_error(&state, &username, &folder), &return_to);
+    }
+
+    let branch = if params.branch.is_empty() {
+        client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string())
+    } else {
+        params.branch.clone()
     };
+    if !is_valid_branch_name(&branch) {
+        return error_redirect(jar, format!("Invalid branch name: {}", branch), &dashboard_url(&params.repo, "", ""));
+    }
+    if let Err(e) = client.ensure_branch(&owner, &repo_name, &branch).await {
+        return error_redirect(jar, format!("Create folder failed: {}", e), &return_to);
+    }
+
+    match client.create_folder(&owner, &repo_name, &branch, &folder).await {
+        // Open the new folder
+        Ok(_) => Redirect::to(&dashboard_url(&params.repo, &params.branch, &folder)).into_response(),
+        Err(e) => error_redirect(jar, format!("Create folder failed: {}", e), &return_to),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct MoveParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+    // Folder the dashboard was showing, to return to afterwards
+    #[serde(default)]
+    folder: String,
+    #[serde(default, rename = "path")]
+    paths: Vec<String>,
+    // Rename a single file or folder in place...
+    #[serde(default)]
+    new_name: String,
+    // ...or move every selected path into this folder ("" is the root)
+    #[serde(default)]
+    destination: Option<String>,
+}
+
+struct MovedFile {
+    from: String,
+    to: String,
+    size: u64,
+}
 
-    // Verify ownership (simple check: repo starts with username)
-    // Or just let GitHub API handle permission errors
-    let parts: Vec<&str> = params.repo.split('/').collect();
-    let owner = if parts.len() == 2 { parts[0] } else { &username };
-    let repo_name = if parts.len() == 2 { parts[1] } else { &params.repo };
+// Work out the Git tree changes for a set of (from, to) moves. Folders move
+// with everything below them. Returns the changes plus every moved file.
+fn plan_moves(
+    tree: &[TreeEntry],
+    moves: &[(String, String)],
+) -> Result<(Vec<TreeChange>, Vec<MovedFile>), String> {
+    let existing: HashMap<&str, &TreeEntry> = tree.iter().map(|e| (e.path.as_str(), e)).collect();
+    let mut changes = Vec::new();
+    let mut moved = Vec::new();
 
-    match client.delete_file(owner, repo_name, &params.path, &params.sha).await {
-        Ok(_) => {
-            // Redirect back to dashboard
-            Redirect::to("/dashboard").into_response()
+    // A selection inside another selected folder already moves with that
+    // folder, and a path selected twice moves once
+    let moves = moves.iter().enumerate().filter(|(i, (from, _))| {
+        !moves.iter().enumerate().any(|(j, (other, _))| {
+            if other == from { j < *i } else { in_folder(other, from) }
+        })
+    });
+
+    for (_, (from, to)) in moves {
+        if from == to {
+            continue;
         }
-        Err(e) => {
-            let mut cookie = Cookie::new("upload_error", format!("Delete failed: {}", e));
-            cookie.set_path("/");
-            cookie.set_http_only(true);
-            (jar.add(cookie), Redirect::to("/dashboard")).into_response()
+        let entry = existing.get(from.as_str()).ok_or_else(|| format!("{} does not exist", from))?;
+
+        let files: Vec<(&TreeEntry, String)> = if entry.kind == "tree" {
+            if to.starts_with(&format!("{}/", from)) {
+                return Err(format!("Cannot move {} into itself", from));
+            }
+            tree.iter()
+                .filter(|e| e.kind == "blob")
+                .filter_map(|e| {
+                    e.path.strip_prefix(&format!("{}/", from))
+                        .map(|rest| (e, format!("{}/{}", to, rest)))
+                })
+                .collect()
+        } else {
+            vec![(*entry, to.clone())]
+        };
+
+        for (file, target) in files {
+            if existing.contains_key(target.as_str()) || moved.iter().any(|m: &MovedFile| m.to == target) {
+                return Err(format!("{} already exists", target));
+            }
+            changes.push(TreeChange::put(target.clone(), file.mode.clone(), file.sha.clone()));
+            changes.push(TreeChange::delete(file.path.clone()));
+            moved.push(MovedFile { from: file.path.clone(), to: target, size: file.size.unwrap_or(0) });
+        }
+    }
+
+    Ok((changes, moved))
+}
+
+pub async fn move_files(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    ExtraForm(params): ExtraForm<MoveParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let folder = normalize_repo_path(&params.folder).unwrap_or_default();
+    let return_to = dashboard_url(&params.repo, &params.branch, &folder);
+
+    let mut paths = Vec::new();
+    for path in &params.paths {
+        match normalize_repo_path(path) {
+            Ok(p) if !p.is_empty() => paths.push(p),
+            Ok(_) => {}
+            Err(e) => return error_redirect(jar, e, &return_to),
+        }
+    }
+    if paths.is_empty() {
+        return error_redirect(jar, "No files selected.", &return_to);
+    }
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    let branch = if params.branch.is_empty() {
+        client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string())
+    } else {
+        params.branch.clone()
+    };
+
+    let tree = match client.get_tree(&owner, &repo_name, &branch).await {
+        Ok(tree) => tree,
+        Err(e) => return error_redirect(jar, format!("Move failed: {}", e), &return_to),
+    };
+
+    // Resolve the target path of every selected entry
+    let new_name = params.new_name.trim();
+    let mut moves = Vec::new();
+    if !new_name.is_empty() {
+        if paths.len() != 1 {
+            return error_redirect(jar, "Select exactly one file to rename.", &return_to);
+        }
+        let from = paths.remove(0);
+        let is_dir = tree.iter().any(|e| e.path == from && e.kind == "tree");
+        let name = if is_dir {
+            sanitize_folder_path(new_name, state.transliterate_filenames).unwrap_or_default().replace('/', "-")
+        } else {
+            let name = sanitize_filename(new_name, state.transliterate_filenames);
+            // Keep the original extension if the new name doesn't have one
+            match (name.contains('.'), from.rsplit_once('.')) {
+                (false, Some((_, ext))) if !ext.contains('/') => format!("{}.{}", name, ext),
+                _ => name,
+            }
+        };
+        if name.is_empty() {
+            return error_redirect(jar, "New name is empty.", &return_to);
+        }
+        let to = match from.rsplit_once('/') {
+            Some((parent, _)) => format!("{}/{}", parent, name),
+            None => name,
+        };
+        moves.push((from, to));
+    } else if let Some(destination) = &params.destination {
+        let destination = match sanitize_folder_path(destination, state.transliterate_filenames) {
+            Ok(d) => d,
+            Err(e) => return error_redirect(jar, e, &return_to),
+        };
+        for from in paths {
+            let name = from.rsplit('/').next().unwrap_or(&from).to_string();
+            let to = if destination.is_empty() { name } else { format!("{}/{}", destination, name) };
+            moves.push((from, to));
+        }
+    } else {
+        return error_redirect(jar, "Choose a new name or a destination folder.", &return_to);
+    }
+    for (from, to) in &moves {
+        if let Some(path) = [from, to].into_iter().find(|p| !path_allowed(&state, &username, p)) {
+            return error_redirect(jar, outside_folder_error(&state, &username, path), &return_to);
+        }
+    }
+
+    let (changes, moved) = match plan_moves(&tree, &moves) {
+        Ok(plan) => plan,
+        Err(e) => return error_redirect(jar, format!("Move failed: {}", e), &return_to),
+    };
+    if changes.is_empty() {
+        return Redirect::to(&return_to).into_response();
+    }
+
+    let message = if moves.len() == 1 {
+        format!("Move {} to {} via RustPic", moves[0].0, moves[0].1)
+    } else {
+        format!("Move {} files via RustPic", moved.len())
+    };
+    let result = client.commit_changes(&owner, &repo_name, &branch, &changes, &message).await
+        .map_err(|e| e.to_string());
+    if let Err(e) = result {
+        return error_redirect(jar, format!("Move failed: {}", e), &return_to);
+    }
+
+    // Keep the duplicate finder's index pointing at the new paths
+    let renamed: Vec<(String, String)> = moved.iter().map(|m| (m.from.clone(), m.to.clone())).collect();
+    state.phash_index.rename_paths(&owner, &repo_name, &branch, &renamed);
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.unwrap_or_default();
+
+    // The old paths are gone; drop them from the CDN cache
+    let old_paths: Vec<String> = moved.iter().map(|m| m.from.clone()).collect();
+    let purge = state.cdn.purge(&owner, &repo_name, &branch, branch == default_branch, &old_paths).await;
+
+    // Show the new links (first few for bulk moves, to keep the cookie small)
+    let serves_pages = branch == default_branch && is_pages_repo(&owner, &repo_name);
+    let links: Vec<String> = moved.iter()
+        .take(10)
+        .map(|m| build_links(&owner, &repo_name, &branch, &m.to, m.size, serves_pages).cdn_link)
+        .collect();
+    let first = &moved[0];
+    let first_links = build_links(&owner, &repo_name, &branch, &first.to, first.size, serves_pages);
+    let notice = if moved.len() == 1 {
+        format!("Moved to {}", first.to)
+    } else {
+        format!("Moved {} files", moved.len())
+    };
+    result_redirect(jar, serde_json::json!({
+        "cdn_link": first_links.cdn_link,
+        "pages_link": first_links.pages_link,
+        "notice": notice,
+        "purge": purge.summary(),
+        "purge_ok": purge.is_success(),
+        "links": if moved.len() > 1 { links } else { vec![] },
+    }), &return_to)
+}
+
+// Expand selected paths into the files to delete; a folder means everything
+// below it, including its placeholder
+fn plan_deletes(tree: &[TreeEntry], paths: &[String]) -> Result<Vec<String>, String> {
+    let mut files: Vec<String> = Vec::new();
+    for path in paths {
+        let entry = tree.iter().find(|e| e.path == *path).ok_or_else(|| format!("{} does not exist", path))?;
+        if entry.kind == "tree" {
+            let prefix = format!("{}/", path);
+            files.extend(tree.iter()
+                .filter(|e| e.kind == "blob" && e.path.starts_with(&prefix))
+                .map(|e| e.path.clone()));
+        } else {
+            files.push(entry.path.clone());
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+#[derive(Deserialize)]
+pub struct BulkDeleteParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+    #[serde(default)]
+    folder: String,
+    #[serde(default, rename = "path")]
+    paths: Vec<String>,
+}
+
+// Delete every selected file and folder in a single commit
+pub async fn delete_selected(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    ExtraForm(params): ExtraForm<BulkDeleteParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let folder = normalize_repo_path(&params.folder).unwrap_or_default();
+    let return_to = dashboard_url(&params.repo, &params.branch, &folder);
+
+    let mut paths = Vec::new();
+    for path in &params.paths {
+        match normalize_repo_path(path) {
+            Ok(p) if !p.is_empty() => paths.push(p),
+            Ok(_) => {}
+            Err(e) => return error_redirect(jar, e, &return_to),
+        }
+    }
+    if paths.is_empty() {
+        return error_redirect(jar, "No files selected.", &return_to);
+    }
+    if let Some(path) = paths.iter().find(|p| !path_allowed(&state, &username, p)) {
+        return error_redirect(jar, outside_folder_error(&state, &username, path), &return_to);
+    }
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string());
+    let branch = if params.branch.is_empty() { default_branch.clone() } else { params.branch.clone() };
+
+    let tree = match client.get_tree(&owner, &repo_name, &branch).await {
+        Ok(tree) => tree,
+        Err(e) => return error_redirect(jar, format!("Delete failed: {}", e), &return_to),
+    };
+    let files = match plan_deletes(&tree, &paths) {
+        Ok(files) if !files.is_empty() => files,
+        Ok(_) => return Redirect::to(&return_to).into_response(),
+        Err(e) => return error_redirect(jar, format!("Delete failed: {}", e), &return_to),
+    };
+
+    let result = client.delete_files(&owner, &repo_name, &branch, &files).await
+        .map_err(|e| e.to_string());
+    if let Err(e) = result {
+        return error_redirect(jar, format!("Delete failed: {}", e), &return_to);
+    }
+
+    state.phash_index.remove_paths(&owner, &repo_name, &branch, &files);
+
+    let public: Vec<String> = files.iter()
+        .filter(|f| !f.ends_with(FOLDER_PLACEHOLDER))
+        .cloned()
+        .collect();
+    let purge = state.cdn.purge(&owner, &repo_name, &branch, branch == default_branch, &public).await;
+
+    result_redirect(jar, serde_json::json!({
+        "notice": if public.len() == 1 { format!("Deleted {}", public[0]) } else { format!("Deleted {} files", public.len()) },
+        "purge": purge.summary(),
+        "purge_ok": purge.is_success(),
+    }), &return_to)
+}
+
+// Overwrite an existing file with new content, keeping its path (and so its
+// links), then purge the jsDelivr cache for it
+pub async fn replace_file(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    mut multipart: Multipart,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let mut repo = String::new();
+    let mut branch = String::new();
+    let mut folder = String::new();
+    let mut path = String::new();
+    let mut sha = String::new();
+    let mut file_content = Vec::new();
+
+    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+        let name = field.name().unwrap_or("").to_string();
+        if name == "file" {
+            match field.bytes().await {
+                Ok(data) => file_content = data.to_vec(),
+                Err(e) => {
+                    return error_redirect(jar, format!("Error reading file: {}", e), &dashboard_url(&repo, &branch, &folder));
+                }
+            }
+        } else {
+            let value = field.text().await.unwrap_or_default();
+            match name.as_str() {
+                "repo" => repo = value,
+                "branch" => branch = value,
+                "folder" => folder = value,
+                "path" => path = value,
+                "sha" => sha = value,
+                _ => {}
+            }
         }
     }
+
+    let folder = normalize_repo_path(&folder).unwrap_or_default();
+    let return_to = dashboard_url(&repo, &branch, &folder);
+
+    let path = match normalize_repo_path(&path) {
+        Ok(p) if !p.is_empty() => p,
+        _ => return error_redirect(jar, "Invalid file path.", &return_to),
+    };
+    if !path_allowed(&state, &username, &path) {
+        return error_redirect(jar, outside_folder_error(&state, &username, &path), &return_to);
+    }
+    if file_content.is_empty() {
+        return error_redirect(jar, "Failed to read file content. The file may be empty or corrupted.", &return_to);
+    }
+    if file_content.len() > 50 * 1024 * 1024 {
+        return error_redirect(jar, "File too large. Maximum size is 50MB.", &return_to);
+    }
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&repo), &username);
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string());
+    let branch = if branch.is_empty() { default_branch.clone() } else { branch };
+
+    let content_base64 = general_purpose::STANDARD.encode(&file_content);
+    let result = client.replace_file(&owner, &repo_name, &branch, &path, &sha, content_base64).await
+        .map_err(|e| e.to_string());
+    if let Err(e) = result {
+        return error_redirect(jar, e, &return_to);
+    }
+
+    // The old hash no longer describes this path
+    state.phash_index.remove_paths(&owner, &repo_name, &branch, std::slice::from_ref(&path));
+
+    let purge = state.cdn.purge(&owner, &repo_name, &branch, branch == default_branch, std::slice::from_ref(&path)).await;
+
+    let links = client.links_for(&owner, &repo_name, &branch, &path, file_content.len() as u64).await;
+    result_redirect(jar, serde_json::json!({
+        "cdn_link": links.cdn_link,
+        "pages_link": links.pages_link,
+        "notice": format!("Replaced {}", path),
+        "purge": purge.summary(),
+        "purge_ok": purge.is_success(),
+    }), &return_to)
+}
+
+// Versions listed on the history page; each needs one API call for its size
+const HISTORY_LIMIT: usize = 30;
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    repo: Option<String>,
+    branch: Option<String>,
+    path: String,
+}
+
+// Commit history of a single file, with links pinned to each commit
+// Size of `path` at a commit, or None where the commit removed it
+async fn file_size_at(client: &GitHubClient, owner: &str, repo: &str, commit_sha: String, path: &str) -> Option<u64> {
+    client.file_at(owner, repo, &commit_sha, path).await
+        .ok()
+        .flatten()
+        .map(|(_, size)| size)
+}
+
+pub async fn file_history(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<HistoryParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, params.repo.as_deref(), &username);
+    let branch = match params.branch.filter(|b| is_valid_branch_name(b)) {
+        Some(branch) => branch,
+        None => client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string()),
+    };
+    let path = match normalize_repo_path(&params.path) {
+        Ok(p) if !p.is_empty() => p,
+        _ => return error_redirect(jar, "Invalid file path.", &dashboard_url(params.repo.as_deref().unwrap_or(""), &branch, "")),
+    };
+    if !path_allowed(&state, &username, &path) {
+        let error = outside_folder_error(&state, &username, &path);
+        return error_redirect(jar, error, &dashboard_url(params.repo.as_deref().unwrap_or(""), &branch, ""));
+    }
+    let folder = path.rsplit_once('/').map(|(parent, _)| parent.to_string()).unwrap_or_default();
+
+    let mut error = None;
+    let commits = client.list_commits(&owner, &repo_name, &branch, Some(&path), None, HISTORY_LIMIT).await
+        .map_err(|e| e.to_string());
+    let commits = match commits {
+        Ok(commits) => commits,
+        Err(e) => {
+            error = Some(format!("Failed to load history: {}", e));
+            vec![]
+        }
+    };
+
+    // Raw links of private repositories need authentication, so they can't
+    // be previews there
+    let previews = file_category(&path) == "image"
+        && !client.is_private(&owner, &repo_name).await.unwrap_or(true);
+
+    // Sizes come from one lookup per commit; run them a few at a time
+    let shas: Vec<String> = commits.iter().map(|c| c.sha.clone()).collect();
+    let sizes: Vec<Option<u64>> = stream::iter(shas)
+        .map(|sha| file_size_at(&client, &owner, &repo_name, sha, &path))
+        .buffered(8)
+        .collect()
+        .await;
+
+    let mut versions = Vec::new();
+    for (commit, size) in commits.into_iter().zip(sizes) {
+        let date = DateTime::parse_from_rfc3339(&commit.date)
+            .map(|d| d.with_timezone(&Utc).format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or(commit.date);
+        // A commit SHA in place of the branch gives a link that never changes
+        let pinned_link = build_links(&owner, &repo_name, &commit.sha, &path, size.unwrap_or(0), false).cdn_link;
+        let preview_url = (previews && size.is_some()).then(|| {
+            format!("https://raw.githubusercontent.com/{}/{}/{}/{}", owner, repo_name, commit.sha, encode_path(&path))
+        });
+        versions.push(FileVersion {
+            commit_sha: commit.sha,
+            message: commit.message,
+            date,
+            size,
+            pinned_link,
+            preview_url,
+        });
+    }
+
+    let template = HistoryTemplate {
+        username,
+        repo: format!("{}/{}", owner, repo_name),
+        branch,
+        path,
+        folder,
+        versions,
+        error,
+        version: crate::ASSET_VERSION.to_string(),
+    };
+
+    Html(template.to_string()).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct TrashParams {
+    repo: Option<String>,
+    branch: Option<String>,
+}
+
+// Files deleted via RustPic within the retention period, read from the
+// branch's commit history
+pub async fn trash(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TrashParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, params.repo.as_deref(), &username);
+    let branch = match params.branch.filter(|b| is_valid_branch_name(b)) {
+        Some(branch) => branch,
+        None => client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string()),
+    };
+
+    let mut error = None;
+    let mut new_jar = jar.clone();
+    if let Some(error_cookie) = jar.get("upload_error") {
+        error = Some(error_cookie.value().to_string());
+        new_jar = new_jar.remove(Cookie::build("upload_error"));
+    }
+
+    let retention_days = state.trash_retention_days;
+    let now = Utc::now();
+    let since = (now - Duration::days(retention_days)).to_rfc3339_opts(SecondsFormat::Secs, true);
+    let deleted = client.list_deleted_files(&owner, &repo_name, &branch, &since).await
+        .map_err(|e| e.to_string());
+    let deleted = match deleted {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            error = Some(format!("Failed to load deleted files: {}", e));
+            DeletedFiles { files: vec![], truncated: false }
+        }
+    };
+
+    let items = deleted.files
+        .into_iter()
+        .filter(|file| path_allowed(&state, &username, &file.path))
+        .map(|file| {
+            let deleted_at = DateTime::parse_from_rfc3339(&file.deleted_at)
+                .map(|d| d.with_timezone(&Utc))
+                .unwrap_or(now);
+            // The parent commit still has the file, so raw links to it keep working
+            let preview_url = (file_category(&file.path) == "image").then(|| {
+                format!("https://raw.githubusercontent.com/{}/{}/{}/{}", owner, repo_name, file.parent_sha, encode_path(&file.path))
+            });
+            TrashItem {
+                deleted_at: deleted_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+                days_left: (retention_days - (now - deleted_at).num_days()).max(0),
+                parent_sha: file.parent_sha,
+                preview_url,
+                path: file.path,
+            }
+        })
+        .collect();
+
+    let template = TrashTemplate {
+        username,
+        repo: format!("{}/{}", owner, repo_name),
+        branch,
+        retention_days,
+        items,
+        incomplete: deleted.truncated,
+        commit_limit: crate::github::TRASH_COMMIT_LIMIT,
+        error,
+        version: crate::ASSET_VERSION.to_string(),
+    };
+
+    (new_jar, Html(template.to_string())).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RestoreParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+    path: String,
+    parent: String,
+}
+
+// Bring a deleted file back by committing its old blob at the same path
+pub async fn restore_file(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<RestoreParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string());
+    let branch = if params.branch.is_empty() { default_branch.clone() } else { params.branch.clone() };
+    let trash_url = repo_page_url("/trash", &params.repo, &params.branch);
+
+    let path = match normalize_repo_path(&params.path) {
+        Ok(p) if !p.is_empty() => p,
+        _ => return error_redirect(jar, "Invalid file path.", &trash_url),
+    };
+    if !path_allowed(&state, &username, &path) {
+        return error_redirect(jar, outside_folder_error(&state, &username, &path), &trash_url);
+    }
+    if params.parent.len() != 40 || !params.parent.chars().all(|c| c.is_ascii_hexdigit()) {
+        return error_redirect(jar, "Invalid commit.", &trash_url);
+    }
+
+    let current = client.file_at(&owner, &repo_name, &branch, &path).await.map_err(|e| e.to_string());
+    match current {
+        Ok(None) => {}
+        Ok(Some(_)) => return error_redirect(jar, format!("{} already exists, delete or rename it first.", path), &trash_url),
+        Err(e) => return error_redirect(jar, format!("Restore failed: {}", e), &trash_url),
+    }
+
+    let previous = client.file_at(&owner, &repo_name, &params.parent, &path).await.map_err(|e| e.to_string());
+    let (sha, size) = match previous {
+        Ok(Some(file)) => file,
+        Ok(None) => return error_redirect(jar, format!("{} was not found in the commit before its deletion.", path), &trash_url),
+        Err(e) => return error_redirect(jar, format!("Restore failed: {}", e), &trash_url),
+    };
+
+    // The blob is still in the repository, so no content needs uploading
+    let changes = [TreeChange::put(path.clone(), "100644".to_string(), sha)];
+    let result = client.commit_changes(&owner, &repo_name, &branch, &changes, &format!("Restore {} via RustPic", path)).await
+        .map_err(|e| e.to_string());
+    if let Err(e) = result {
+        return error_redirect(jar, format!("Restore failed: {}", e), &trash_url);
+    }
+
+    // jsDelivr may have cached a 404 since the delete
+    let purge = state.cdn.purge(&owner, &repo_name, &branch, branch == default_branch, std::slice::from_ref(&path)).await;
+
+    let links = build_links(&owner, &repo_name, &branch, &path, size, branch == default_branch && is_pages_repo(&owner, &repo_name));
+    let folder = path.rsplit_once('/').map(|(parent, _)| parent).unwrap_or("");
+    result_redirect(jar, serde_json::json!({
+        "cdn_link": links.cdn_link,
+        "pages_link": links.pages_link,
+        "notice": format!("Restored {}", path),
+        "purge": purge.summary(),
+        "purge_ok": purge.is_success(),
+    }), &dashboard_url(&params.repo, &params.branch, folder))
+}
+
+#[derive(Deserialize)]
+pub struct DuplicatesParams {
+    repo: Option<String>,
+    branch: Option<String>,
+    threshold: Option<u32>,
+}
+
+pub async fn duplicates(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DuplicatesParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, params.repo.as_deref(), &username);
+    // The index is shared by all users; only show it to those who can read
+    // the repository
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.map_err(|e| e.to_string());
+    let default_branch = match default_branch {
+        Ok(branch) => branch,
+        Err(e) => return error_redirect(jar, format!("Cannot open {}/{}: {}", owner, repo_name, e), "/dashboard"),
+    };
+    let branch = params.branch.filter(|b| !b.is_empty()).unwrap_or(default_branch);
+    let threshold = params.threshold.unwrap_or(phash::DEFAULT_THRESHOLD).min(32);
+
+    let mut error = None;
+    let mut new_jar = jar.clone();
+    if let Some(error_cookie) = jar.get("upload_error") {
+        error = Some(error_cookie.value().to_string());
+        new_jar = new_jar.remove(Cookie::build("upload_error"));
+    }
+
+    let index = &state.phash_index;
+    let (status, scanning) = match index.status(&owner, &repo_name, &branch) {
+        None => (None, false),
+        Some(ScanStatus::Running { done, total }) => (Some(format!("Scanning… {} of {} images hashed", done, total)), true),
+        Some(ScanStatus::Finished) => (Some(format!("{} images indexed", index.image_count(&owner, &repo_name, &branch))), false),
+        Some(ScanStatus::Failed(e)) => (Some(format!("Last scan failed: {}", e)), false),
+    };
+
+    let template = DuplicatesTemplate {
+        raw_base: format!("https://raw.githubusercontent.com/{}/{}/{}", owner, repo_name, encode_path(&branch)),
+        groups: allowed_groups(&state, &username, index.groups(&owner, &repo_name, &branch, threshold)),
+        repo: format!("{}/{}", owner, repo_name),
+        branch,
+        username,
+        threshold,
+        status,
+        scanning,
+        error,
+        version: crate::ASSET_VERSION.to_string(),
+    };
+
+    (new_jar, Html(template.to_string())).into_response()
+}
+
+// Drop images outside the user's folder, and groups that no longer have a
+// duplicate left to show
+fn allowed_groups(state: &AppState, username: &str, groups: Vec<Vec<HashedImage>>) -> Vec<Vec<HashedImage>> {
+    groups
+        .into_iter()
+        .map(|group| group.into_iter().filter(|image| path_allowed(state, username, &image.path)).collect::<Vec<_>>())
+        .filter(|group| group.len() >= 2)
+        .collect()
+}
+
+#[derive(Deserialize)]
+pub struct ScanParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+}
+
+pub async fn scan_duplicates(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<ScanParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    // The installation token below can read any repository the app is on, so
+    // the user's own token must reach this one first
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.map_err(|e| e.to_string());
+    let default_branch = match default_branch {
+        Ok(branch) => branch,
+        Err(e) => return error_redirect(jar, format!("Cannot scan {}/{}: {}", owner, repo_name, e), "/dashboard"),
+    };
+    // As a GitHub App, scan with an installation token so the background job
+    // doesn't depend on the user's token staying valid
+    let client = match &state.github_app {
+        Some(app) => match app.repo_client(&owner, &repo_name).await.map_err(|e| e.to_string()) {
+            Ok(Some(app_client)) => app_client,
+            Ok(None) => client,
+            Err(e) => {
+                println!("Scanning {}/{} with the user's token: {}", owner, repo_name, e);
+                client
+            }
+        },
+        None => client,
+    };
+    let branch = if params.branch.is_empty() { default_branch } else { params.branch.clone() };
+    state.phash_index.spawn_scan(client, owner, repo_name, branch);
+    Redirect::to(&repo_page_url("/duplicates", &params.repo, &params.branch)).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct DeleteDuplicatesParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+    #[serde(default, rename = "path")]
+    paths: Vec<String>,
+}
+
+pub async fn delete_duplicates(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    ExtraForm(params): ExtraForm<DeleteDuplicatesParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let duplicates_url = repo_page_url("/duplicates", &params.repo, &params.branch);
+    if params.paths.is_empty() {
+        return error_redirect(jar, "No files selected.", &duplicates_url);
+    }
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    // Delete on the branch that was scanned, so the comparison and the
+    // delete see the same tree
+    let default_branch = client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string());
+    let branch = if params.branch.is_empty() { default_branch.clone() } else { params.branch.clone() };
+    let mut deleted = Vec::new();
+    let mut failures = Vec::new();
+
+    for path in params.paths {
+        if !path_allowed(&state, &username, &path) {
+            failures.push(outside_folder_error(&state, &username, &path));
+        } else if state.phash_index.find(&owner, &repo_name, &branch, &path).is_some() {
+            deleted.push(path);
+        } else {
+            failures.push(format!("{}: not in index, rescan and try again", path));
+        }
+    }
+
+    if !deleted.is_empty() {
+        let result = client.delete_files(&owner, &repo_name, &branch, &deleted).await.map_err(|e| e.to_string());
+        if let Err(e) = result {
+            return error_redirect(jar, format!("Delete failed: {}", e), &duplicates_url);
+        }
+    }
+
+    state.phash_index.remove_paths(&owner, &repo_name, &branch, &deleted);
+
+    let purge = state.cdn.purge(&owner, &repo_name, &branch, branch == default_branch, &deleted).await;
+
+    if !failures.is_empty() {
+        error_redirect(jar, format!("Delete failed for {}", failures.join("; ")), &duplicates_url)
+    } else if !purge.is_success() {
+        error_redirect(jar, purge.summary(), &duplicates_url)
+    } else {
+        Redirect::to(&duplicates_url).into_response()
+    }
+}
+
+// Cookie carrying a newly created upload link's URL to the links page
+const NEW_UPLOAD_LINK_COOKIE: &str = "new_upload_link";
+
+fn format_mb(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1024.0 / 1024.0)
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|d| d.format("%Y-%m-%d %H:%M UTC").to_string())
+        .unwrap_or_default()
+}
+
+fn describe_types(types: &[String]) -> String {
+    if types.is_empty() {
+        "any file".to_string()
+    } else {
+        types.iter().map(|t| format!("{}s", t)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+// Origin for absolute links: PUBLIC_URL when configured, otherwise the
+// scheme and host the browser used to reach RustPic
+fn public_origin(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(url) = &state.public_url {
+        return url.clone();
+    }
+    let host = headers
+        .get("host")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost:3002");
+    format!("{}://{}", if state.secure_cookies { "https" } else { "http" }, host)
+}
+
+#[derive(Deserialize)]
+pub struct UploadLinksParams {
+    repo: Option<String>,
+}
+
+pub async fn upload_links(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<UploadLinksParams>,
+) -> Response {
+    let (_client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, params.repo.as_deref(), &username);
+
+    let mut error = None;
+    let mut new_link = None;
+    let mut new_jar = jar.clone();
+    if let Some(error_cookie) = jar.get("upload_error") {
+        error = Some(error_cookie.value().to_string());
+        new_jar = new_jar.remove(Cookie::build("upload_error"));
+    }
+    if let Some(link_cookie) = jar.get(NEW_UPLOAD_LINK_COOKIE) {
+        new_link = Some(link_cookie.value().to_string());
+        new_jar = new_jar.remove(Cookie::build(NEW_UPLOAD_LINK_COOKIE));
+    }
+
+    let links = state.upload_links
+        .list(&username)
+        .into_iter()
+        .map(|link| UploadLinkRow {
+            files: format!("{} / {}", link.files_uploaded, link.limits.max_files),
+            size: format!("{} of {}", format_mb(link.bytes_uploaded), format_mb(link.limits.max_bytes)),
+            types: describe_types(&link.limits.allowed_types),
+            expires: format_timestamp(link.limits.expires_at),
+            expired: link.is_expired(),
+            id: link.id,
+            repo: link.limits.repo,
+            folder: link.limits.folder,
+        })
+        .collect();
+
+    let template = UploadLinksTemplate {
+        folder: user_folder(&state, &username).unwrap_or("uploads").to_string(),
+        username,
+        repo: format!("{}/{}", owner, repo_name),
+        new_link,
+        links,
+        error,
+        server_sessions: state.sessions.is_some(),
+        version: crate::ASSET_VERSION.to_string(),
+    };
+
+    (new_jar, Html(template.to_string())).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct CreateUploadLinkParams {
+    #[serde(default)]
+    repo: String,
+    #[serde(default)]
+    branch: String,
+    folder: String,
+    max_files: u32,
+    max_mb: u64,
+    expires_hours: i64,
+    #[serde(default, rename = "type")]
+    types: Vec<String>,
+}
+
+pub async fn create_upload_link(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    ExtraForm(params): ExtraForm<CreateUploadLinkParams>,
+) -> Response {
+    let (client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let (owner, repo_name) = storage_repo(&state, &jar, Some(&params.repo), &username);
+    let full_repo = format!("{}/{}", owner, repo_name);
+    let links_url = repo_page_url("/links", &full_repo, "");
+
+    // As a GitHub App, uploads through the link use the installation token,
+    // which may reach repositories the creator can't; only links into
+    // repositories the creator can push to are made
+    match client.probe_write_access(&owner, &repo_name).await.map_err(|e| e.to_string()) {
+        Ok(WriteAccess::Allowed) => {}
+        Ok(WriteAccess::Denied(reason)) => {
+            return error_redirect(jar, format!("You can't write to {} ({}).", full_repo, reason), &links_url);
+        }
+        Ok(WriteAccess::NotFound) => {
+            return error_redirect(jar, format!("{} does not exist or you can't access it.", full_repo), &links_url);
+        }
+        Err(e) => return error_redirect(jar, format!("Could not check access to {}: {}", full_repo, e), &links_url),
+    }
+
+    let folder = match sanitize_folder_path(&params.folder, state.transliterate_filenames) {
+        Ok(folder) => folder,
+        Err(e) => return error_redirect(jar, e, &links_url),
+    };
+    if !path_allowed(&state, &username, &folder) {
+        return error_redirect(jar, outside_folder_error(&state, &username, &folder), &links_url);
+    }
+    if !params.branch.is_empty() && !is_valid_branch_name(&params.branch) {
+        return error_redirect(jar, format!("Invalid branch name: {}", params.branch), &links_url);
+    }
+    if !(1..=1000).contains(&params.max_files) {
+        return error_redirect(jar, "The file limit must be between 1 and 1000.", &links_url);
+    }
+    if !(1..=1024).contains(&params.max_mb) {
+        return error_redirect(jar, "The size limit must be between 1 and 1024 MB.", &links_url);
+    }
+    if !(1..=720).contains(&params.expires_hours) {
+        return error_redirect(jar, "Links can last between 1 hour and 30 days.", &links_url);
+    }
+    if let Some(unknown) = params.types.iter().find(|t| !["image", "document", "archive"].contains(&t.as_str())) {
+        return error_redirect(jar, format!("Unknown file type: {}", unknown), &links_url);
+    }
+
+    // Uploads through the link use the credentials of this session
+    let credentials = match current_session(&state, &jar) {
+        Some(session) => session.credentials,
+        None => return Redirect::to("/logout").into_response(),
+    };
+    let limits = upload_links::LinkLimits {
+        repo: full_repo,
+        branch: params.branch,
+        folder,
+        max_files: params.max_files,
+        max_bytes: params.max_mb * 1024 * 1024,
+        allowed_types: params.types,
+        expires_at: (Utc::now() + Duration::hours(params.expires_hours)).timestamp(),
+    };
+    let token = state.upload_links.create(&username, limits, &credentials);
+    println!("{} created an upload link", username);
+
+    let mut cookie = Cookie::new(NEW_UPLOAD_LINK_COOKIE, format!("{}/u/{}", public_origin(&state, &headers), token));
+    cookie.set_path("/");
+    (jar.add(cookie), Redirect::to(&links_url)).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RevokeUploadLinkParams {
+    id: String,
+    #[serde(default)]
+    repo: String,
+}
+
+pub async fn revoke_upload_link(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Form(params): Form<RevokeUploadLinkParams>,
+) -> Response {
+    let (_client, username) = match authenticate(&state, &jar).await {
+        Ok(auth) => auth,
+        Err(redirect) => return redirect,
+    };
+
+    let links_url = repo_page_url("/links", &params.repo, "");
+    if !state.upload_links.revoke(&username, &params.id) {
+        return error_redirect(jar, "Upload link not found.", &links_url);
+    }
+    Redirect::to(&links_url).into_response()
+}
+
+// GitHub client an upload link acts with: the shared token in single-tenant
+// mode, the app installation in GitHub App mode (user tokens expire and
+// their single-use refresh token stays with the session), otherwise the
+// creator's stored token
+async fn upload_link_client(state: &AppState, link: &UploadLink, credentials: Credentials, owner: &str, repo: &str) -> Result<GitHubClient, String> {
+    if let Some(tenant) = &state.single_tenant {
+        if tenant.user(&link.owner).is_none() || !path_allowed(state, &link.owner, &link.limits.folder) {
+            return Err("This upload link is no longer valid.".to_string());
+        }
+        return Ok(GitHubClient::new(state.http.clone(), tenant.token.clone()));
+    }
+    if let Some(app) = &state.github_app {
+        return match app.repo_client(owner, repo).await.map_err(|e| e.to_string()) {
+            Ok(Some(client)) => Ok(client),
+            Ok(None) => Err(format!("RustPic is not installed on {}/{}.", owner, repo)),
+            Err(e) => Err(e),
+        };
+    }
+    Ok(GitHubClient::new(state.http.clone(), credentials.token))
+}
+
+// Public upload page behind an upload link
+pub async fn share_upload_page(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+) -> Response {
+    let mut error = None;
+    let mut notice = None;
+    let mut new_jar = jar.clone();
+    if let Some(error_cookie) = jar.get("upload_error") {
+        error = Some(error_cookie.value().to_string());
+        new_jar = new_jar.remove(Cookie::build("upload_error"));
+    }
+    if let Some(result_cookie) = jar.get("upload_result") {
+        notice = serde_json::from_str::<serde_json::Value>(result_cookie.value())
+            .ok()
+            .and_then(|result| result["notice"].as_str().map(str::to_string));
+        new_jar = new_jar.remove(Cookie::build("upload_result"));
+    }
+
+    let Some((link, _)) = state.upload_links.get(&token) else {
+        let template = ShareUploadTemplate {
+            owner: String::new(),
+            token: String::new(),
+            files_left: 0,
+            size_left: String::new(),
+            types: String::new(),
+            accept: String::new(),
+            expires: String::new(),
+            notice: None,
+            error: Some("This upload link has expired or was revoked.".to_string()),
+            version: crate::ASSET_VERSION.to_string(),
+        };
+        return (StatusCode::NOT_FOUND, new_jar, Html(template.to_string())).into_response();
+    };
+
+    let accept = link.limits.allowed_types
+        .iter()
+        .flat_map(|t| listing::category_extensions(t))
+        .map(|ext| format!(".{}", ext))
+        .collect::<Vec<_>>()
+        .join(",");
+    let template = ShareUploadTemplate {
+        files_left: link.files_left(),
+        size_left: format_mb(link.bytes_left()),
+        types: describe_types(&link.limits.allowed_types),
+        accept,
+        expires: format_timestamp(link.limits.expires_at),
+        owner: link.owner,
+        token,
+        notice,
+        error,
+        version: crate::ASSET_VERSION.to_string(),
+    };
+
+    (new_jar, Html(template.to_string())).into_response()
+}
+
+// Upload through an upload link; no sign-in, the link's limits apply
+pub async fn share_upload(
+    jar: Cookies,
+    State(state): State<Arc<AppState>>,
+    Path(token): Path<String>,
+    mut multipart: Multipart,
+) -> Response {
+    let page_url = format!("/u/{}", token);
+    let (link, credentials) = match state.upload_links.get(&token) {
+        Some(found) => found,
+        None => return Redirect::to(&page_url).into_response(),
+    };
+
+    let (owner, repo_name) = match parse_repo_name(&link.limits.repo) {
+        Some(repo) => repo,
+        None => return error_redirect(jar, "This upload link is no longer valid.", &page_url),
+    };
+    let client = match upload_link_client(&state, &link, credentials, &owner, &repo_name).await {
+        Ok(client) => client,
+        Err(e) => return error_redirect(jar, e, &page_url),
+    };
+
+    let branch = if link.limits.branch.is_empty() {
+        client.get_default_branch(&owner, &repo_name).await.unwrap_or_else(|_| "main".to_string())
+    } else {
+        link.limits.branch.clone()
+    };
+    let ensured = client.ensure_branch(&owner, &repo_name, &branch).await.map_err(|e| e.to_string());
+    if let Err(e) = ensured {
+        return error_redirect(jar, format!("Upload failed: {}", e), &page_url);
+    }
+
+    let mut uploaded = 0;
+    let mut errors = Vec::new();
+    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = field.file_name().unwrap_or("").to_string();
+        let file_content = match field.bytes().await {
+            Ok(data) => data.to_vec(),
+            Err(e) => {
+                errors.push(format!("Error reading {}: {}", filename, e));
+                break;
+            }
+        };
+        if filename.is_empty() || file_content.is_empty() {
+            continue;
+        }
+
+        let category = file_category(&filename);
+        if !link.limits.allowed_types.is_empty() && !link.limits.allowed_types.iter().any(|t| t == category) {
+            errors.push(format!("{}: this file type is not accepted", filename));
+            continue;
+        }
+        if file_content.len() > 50 * 1024 * 1024 {
+            errors.push(format!("{}: file too large, maximum size is 50MB", filename));
+            continue;
+        }
+        let size = file_content.len() as u64;
+        if let Err(e) = state.upload_links.reserve(&token, size) {
+            errors.push(format!("{}: {}", filename, e));
+            break;
+        }
+
+        let path = timestamped_path(&link.limits.folder, &sanitize_filename(&filename, state.transliterate_filenames));
+        let content_base64 = general_purpose::STANDARD.encode(&file_content);
+        let result = client.upload_file_with_links(&owner, &repo_name, &branch, &path, content_base64, file_content.len()).await
+            .map_err(|e| e.to_string());
+        match result {
+            Ok(_) => {
+                println!("Upload link of {} stored {}", link.owner, path);
+                uploaded += 1;
+            }
+            Err(e) => {
+                state.upload_links.release(&token, size);
+                errors.push(format!("{}: upload failed: {}", filename, e));
+            }
+        }
+    }
+
+    if uploaded == 0 && errors.is_empty() {
+        return error_redirect(jar, "No file selected.", &page_url);
+    }
+    let jar = if errors.is_empty() { jar } else { jar.add(error_cookie(errors.join("; "))) };
+    if uploaded == 0 {
+        return (jar, Redirect::to(&page_url)).into_response();
+    }
+    result_redirect(jar, serde_json::json!({
+        "notice": if uploaded == 1 { "Uploaded 1 file".to_string() } else { format!("Uploaded {} files", uploaded) },
+    }), &page_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, kind: &str) -> TreeEntry {
+        TreeEntry {
+            path: path.to_string(),
+            mode: if kind == "tree" { "040000" } else { "100644" }.to_string(),
+            kind: kind.to_string(),
+            sha: format!("sha-{}", path),
+            size: (kind == "blob").then_some(1),
+        }
+    }
+
+    fn sample_tree() -> Vec<TreeEntry> {
+        vec![
+            entry("a", "tree"),
+            entry("a/one.png", "blob"),
+            entry("a/sub", "tree"),
+            entry("a/sub/two.png", "blob"),
+            entry("b", "tree"),
+            entry("b/one.png", "blob"),
+            entry("top.png", "blob"),
+        ]
+    }
+
+    fn moves(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(from, to)| (from.to_string(), to.to_string())).collect()
+    }
+
+    #[test]
+    fn move_folder_carries_its_files() {
+        let (changes, moved) = plan_moves(&sample_tree(), &moves(&[("a", "c")])).unwrap();
+        let targets: Vec<&str> = moved.iter().map(|m| m.to.as_str()).collect();
+        assert_eq!(targets, ["c/one.png", "c/sub/two.png"]);
+        // One put and one delete per file, keeping the blob
+        assert_eq!(changes.len(), 4);
+        assert_eq!(changes[0].sha.as_deref(), Some("sha-a/one.png"));
+        assert_eq!(changes[1].path, "a/one.png");
+        assert!(changes[1].sha.is_none());
+    }
+
+    #[test]
+    fn move_into_itself_is_rejected() {
+        let err = plan_moves(&sample_tree(), &moves(&[("a", "a/sub/a")])).err().expect("move should be rejected");
+        assert!(err.contains("into itself"), "{}", err);
+    }
+
+    #[test]
+    fn move_to_sibling_with_shared_prefix_is_allowed() {
+        let (_, moved) = plan_moves(&sample_tree(), &moves(&[("a", "ab")])).unwrap();
+        assert_eq!(moved.len(), 2);
+    }
+
+    #[test]
+    fn move_onto_existing_file_is_rejected() {
+        let err = plan_moves(&sample_tree(), &moves(&[("top.png", "b/one.png")])).err().expect("move should be rejected");
+        assert!(err.contains("b/one.png already exists"), "{}", err);
+        // Folder contents colliding with a file already in the target
+        let err = plan_moves(&sample_tree(), &moves(&[("a", "b")])).err().expect("move should be rejected");
+        assert!(err.contains("already exists"), "{}", err);
+    }
+
+    #[test]
+    fn moves_colliding_with_each_other_are_rejected() {
+        let err = plan_moves(&sample_tree(), &moves(&[("a/one.png", "x.png"), ("b/one.png", "x.png")])).err().expect("move should be rejected");
+        assert!(err.contains("x.png already exists"), "{}", err);
+    }
+
+    #[test]
+    fn move_of_folder_and_its_contents_moves_each_file_once() {
+        let selection = moves(&[("a", "c/a"), ("a/one.png", "c/one.png"), ("a", "c/a")]);
+        let (changes, moved) = plan_moves(&sample_tree(), &selection).unwrap();
+        let targets: Vec<&str> = moved.iter().map(|m| m.to.as_str()).collect();
+        assert_eq!(targets, ["c/a/one.png", "c/a/sub/two.png"]);
+        assert_eq!(changes.iter().filter(|c| c.path == "a/one.png").count(), 1);
+    }
+
+    #[test]
+    fn move_of_missing_path_is_rejected() {
+        assert!(plan_moves(&sample_tree(), &moves(&[("missing.png", "x.png")])).is_err());
+    }
+
+    #[test]
+    fn move_to_same_path_is_a_no_op() {
+        let (changes, moved) = plan_moves(&sample_tree(), &moves(&[("top.png", "top.png")])).unwrap();
+        assert!(changes.is_empty() && moved.is_empty());
+    }
+
+    #[test]
+    fn folder_confinement_matches_whole_segments() {
+        assert!(in_folder("alice", "alice"));
+        assert!(in_folder("alice", "alice/photo.png"));
+        assert!(in_folder("users/alice", "users/alice/2024/photo.png"));
+        assert!(!in_folder("alice", "alice2/photo.png"));
+        assert!(!in_folder("alice", "alice2"));
+        assert!(!in_folder("alice", "bob/alice/photo.png"));
+        assert!(!in_folder("alice", ""));
+        assert!(!in_folder("users/alice", "users"));
+    }
+
+    #[test]
+    fn delete_folder_expands_to_its_files() {
+        let paths = vec!["a".to_string(), "a/one.png".to_string(), "top.png".to_string()];
+        let files = plan_deletes(&sample_tree(), &paths).unwrap();
+        assert_eq!(files, ["a/one.png", "a/sub/two.png", "top.png"]);
+    }
+
+    #[test]
+    fn delete_of_missing_path_is_rejected() {
+        let err = plan_deletes(&sample_tree(), &["gone.png".to_string()]).unwrap_err();
+        assert!(err.contains("gone.png does not exist"), "{}", err);
+    }
 }
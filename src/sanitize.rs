@@ -0,0 +1,180 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+// Everything except unreserved characters and `/` gets percent-encoded,
+// matching askama's `urlencode` filter used in the templates
+const PATH_SEGMENT_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+const PATH_SET: &AsciiSet = &PATH_SEGMENT_SET.remove(b'/');
+
+/// Percent-encode a repository path for use in API URLs and public links,
+/// leaving the `/` separators intact.
+pub fn encode_path(path: &str) -> String {
+    utf8_percent_encode(path, PATH_SET).to_string()
+}
+
+// Turn an arbitrary string into a URL-safe slug. Unicode letters and digits
+// (e.g. CJK) are kept; whitespace, reserved characters and symbols such as
+// emoji collapse into single dashes.
+fn slugify(input: &str, transliterate: bool) -> String {
+    let input = if transliterate {
+        deunicode::deunicode(input)
+    } else {
+        input.to_string()
+    };
+
+    let mut slug = String::with_capacity(input.len());
+    for c in input.chars() {
+        if c.is_alphanumeric() || c == '_' || c == '.' {
+            slug.push(c);
+        } else if !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    slug.trim_matches(|c| c == '-' || c == '.' || c == '_').to_string()
+}
+
+/// Sanitize an uploaded file name: drop any client-side directories, slug the
+/// stem and keep a lowercase alphanumeric extension.
+pub fn sanitize_filename(filename: &str, transliterate: bool) -> String {
+    // Browsers may send "C:\fakepath\name.png" or "../name.png"
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+
+    let (stem, ext) = match base.rfind('.') {
+        Some(pos) if pos > 0 => (&base[..pos], Some(&base[pos + 1..])),
+        _ => (base, None),
+    };
+
+    let mut stem = slugify(stem, transliterate);
+    if stem.is_empty() {
+        stem = "file".to_string();
+    }
+
+    let ext: String = ext
+        .unwrap_or("")
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+/// Sanitize a user-supplied folder path. Each segment is slugged, empty and
+/// `.` segments are dropped, and `..` is rejected outright.
+pub fn sanitize_folder_path(path: &str, transliterate: bool) -> Result<String, String> {
+    let mut segments = Vec::new();
+    for segment in path.split(['/', '\\']) {
+        let segment = segment.trim();
+        if segment == ".." {
+            return Err("Folder path must not contain '..'".to_string());
+        }
+        if segment.is_empty() || segment == "." {
+            continue;
+        }
+        let slug = slugify(segment, transliterate);
+        if !slug.is_empty() {
+            segments.push(slug);
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+/// Normalize an existing repository path used for browsing. Unlike
+/// `sanitize_folder_path` the segments are kept verbatim, since they may have
+/// been created outside RustPic; only `..` is rejected, including when
+/// written with a backslash separator.
+pub fn normalize_repo_path(path: &str) -> Result<String, String> {
+    let mut segments = Vec::new();
+    for segment in path.split('/') {
+        if segment.split('\\').any(|part| part == "..") {
+            return Err("Path must not contain '..'".to_string());
+        }
+        if !segment.is_empty() && segment != "." {
+            segments.push(segment);
+        }
+    }
+    Ok(segments.join("/"))
+}
+
+/// Conservative check for a branch name chosen in the dashboard: a subset of
+/// what `git check-ref-format` allows, enough for names like `images` or
+/// `assets/2024`.
+pub fn is_valid_branch_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 100
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+        && !name.contains("..")
+        && !name.contains("//")
+        && !name.starts_with(['/', '.', '-'])
+        && !name.ends_with(['/', '.'])
+        && !name.ends_with(".lock")
+}
+
+/// Split an `owner/name` repository reference, accepting only the characters
+/// GitHub allows in account and repository names.
+pub fn parse_repo_name(full_name: &str) -> Option<(String, String)> {
+    let (owner, name) = full_name.trim().split_once('/')?;
+    let valid = |part: &str| {
+        !part.is_empty()
+            && part.len() <= 100
+            && part != "."
+            && part != ".."
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    if valid(owner) && valid(name) {
+        Some((owner.to_string(), name.to_string()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folder_path_rejects_traversal() {
+        assert!(sanitize_folder_path("..", false).is_err());
+        assert!(sanitize_folder_path("a/../b", false).is_err());
+        assert!(sanitize_folder_path("a\\..\\b", false).is_err());
+        assert!(sanitize_folder_path(" .. /etc", false).is_err());
+    }
+
+    #[test]
+    fn folder_path_is_slugged_and_normalized() {
+        assert_eq!(sanitize_folder_path("/a//./b c/", false).unwrap(), "a/b-c");
+        assert_eq!(sanitize_folder_path("photos\\2024", false).unwrap(), "photos/2024");
+        assert_eq!(sanitize_folder_path("...", false).unwrap(), "");
+        assert_eq!(sanitize_folder_path("相册/旅行", false).unwrap(), "相册/旅行");
+    }
+
+    #[test]
+    fn repo_path_rejects_traversal() {
+        assert!(normalize_repo_path("..").is_err());
+        assert!(normalize_repo_path("a/../b").is_err());
+        assert!(normalize_repo_path("a/..\\b").is_err());
+        assert!(normalize_repo_path("..\\secret").is_err());
+    }
+
+    #[test]
+    fn repo_path_keeps_segments_verbatim() {
+        assert_eq!(normalize_repo_path("/a//./My File.png").unwrap(), "a/My File.png");
+        assert_eq!(normalize_repo_path("a\\b/..c").unwrap(), "a\\b/..c");
+        assert_eq!(normalize_repo_path("").unwrap(), "");
+    }
+
+    #[test]
+    fn filename_drops_client_directories() {
+        assert_eq!(sanitize_filename("C:\\fakepath\\Photo 1.PNG", false), "Photo-1.png");
+        assert_eq!(sanitize_filename("../../etc/passwd", false), "passwd");
+        assert_eq!(sanitize_filename("..", false), "file");
+    }
+}
@@ -0,0 +1,408 @@
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{Duration, Utc};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Cookie holding the opaque session ID
+pub const SESSION_COOKIE: &str = "session";
+// Stateless mode: cookie holding the whole session, encrypted by the cookie jar
+pub const TOKEN_COOKIE: &str = "gh_token";
+
+// GitHub token of a session. User-to-server tokens of a GitHub App expire
+// and come with a refresh token; classic OAuth tokens and PATs don't.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+}
+
+impl Credentials {
+    // A token that doesn't expire
+    pub fn token(token: String) -> Self {
+        Credentials {
+            token,
+            refresh_token: None,
+            expires_at: None,
+        }
+    }
+
+    // Expired or about to: refresh before using it for another request
+    pub fn needs_refresh(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= (Utc::now() + Duration::seconds(60)).timestamp())
+    }
+}
+
+// A signed-in browser: the GitHub login and the credentials used on its behalf
+#[derive(Clone)]
+pub struct Session {
+    pub username: String,
+    pub credentials: Credentials,
+}
+
+// Session as kept in memory and on disk. The credentials are encrypted with
+// AES-256-GCM so a leaked session file does not expose GitHub tokens.
+#[derive(Clone, Serialize, Deserialize)]
+struct StoredSession {
+    username: String,
+    nonce: String,
+    token: String,
+    created_at: i64,
+    expires_at: i64,
+}
+
+// Session carried by TOKEN_COOKIE; the expiry is checked server-side so a
+// copied cookie stops working even if the browser ignores its max-age
+#[derive(Serialize, Deserialize)]
+struct CookieSession {
+    username: String,
+    #[serde(flatten)]
+    credentials: Credentials,
+    session_expires_at: i64,
+}
+
+pub fn to_cookie_value(username: &str, credentials: &Credentials, ttl: Duration) -> String {
+    let session = CookieSession {
+        username: username.to_string(),
+        credentials: credentials.clone(),
+        session_expires_at: (Utc::now() + ttl).timestamp(),
+    };
+    serde_json::to_string(&session).unwrap_or_default()
+}
+
+pub fn from_cookie_value(value: &str) -> Option<Session> {
+    let session: CookieSession = serde_json::from_str(value).ok()?;
+    (session.session_expires_at > Utc::now().timestamp()).then_some(Session {
+        username: session.username,
+        credentials: session.credentials,
+    })
+}
+
+// Where sessions live between requests
+pub enum Backend {
+    // Lost on restart; fine for a single instance
+    Memory,
+    // JSON file rewritten on every change, survives restarts
+    File(PathBuf),
+}
+
+impl Backend {
+    // Parse SESSION_STORE: "memory" or "file:<path>"
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.split_once(':') {
+            None if value == "memory" => Some(Backend::Memory),
+            Some(("file", path)) if !path.is_empty() => Some(Backend::File(PathBuf::from(path))),
+            _ => None,
+        }
+    }
+}
+
+// Encrypts GitHub credentials at rest with AES-256-GCM
+pub struct CredentialCipher {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialCipher {
+    // `secret` is any high-entropy key material; the encryption key is
+    // derived from it so it can be shared with the cookie signing key
+    pub fn new(secret: &[u8]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(b"rustpic session encryption");
+        hasher.update(secret);
+        let cipher = Aes256Gcm::new_from_slice(&hasher.finalize()).expect("SHA-256 output is a valid AES-256 key");
+        CredentialCipher { cipher }
+    }
+
+    // Encrypt credentials with a fresh nonce; returns (nonce, ciphertext) in base64
+    pub fn encrypt(&self, credentials: &Credentials) -> (String, String) {
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let plaintext = serde_json::to_vec(credentials).unwrap_or_default();
+        let ciphertext = self.cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .expect("AES-GCM encryption does not fail for in-memory buffers");
+        (general_purpose::STANDARD.encode(nonce), general_purpose::STANDARD.encode(ciphertext))
+    }
+
+    pub fn decrypt(&self, nonce: &str, ciphertext: &str) -> Option<Credentials> {
+        let nonce = general_purpose::STANDARD.decode(nonce).ok().filter(|n| n.len() == 12)?;
+        let ciphertext = general_purpose::STANDARD.decode(ciphertext).ok()?;
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref()).ok()?;
+        // Sessions saved before refresh tokens were supported hold a bare token
+        serde_json::from_slice(&plaintext)
+            .ok()
+            .or_else(|| String::from_utf8(plaintext).ok().map(Credentials::token))
+    }
+}
+
+pub struct SessionStore {
+    // Keyed by the SHA-256 of the session ID, never the ID itself
+    sessions: Mutex<HashMap<String, StoredSession>>,
+    file: Option<PathBuf>,
+    cipher: CredentialCipher,
+    // Held while a session's token is refreshed, keyed like `sessions`
+    refresh_locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+// SHA-256 hex digest, used so secrets are never kept as map keys
+pub fn id_hash(id: &str) -> String {
+    format!("{:x}", Sha256::digest(id.as_bytes()))
+}
+
+// Random URL-safe identifier with 256 bits of entropy
+pub fn random_token() -> String {
+    let mut id = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut id);
+    general_purpose::URL_SAFE_NO_PAD.encode(id)
+}
+
+impl SessionStore {
+    pub fn new(backend: Backend, secret: &[u8]) -> Self {
+        let file = match backend {
+            Backend::Memory => None,
+            Backend::File(path) => Some(path),
+        };
+        let sessions = file.as_ref().map(|path| load_json(path, "session")).unwrap_or_default();
+
+        SessionStore {
+            sessions: Mutex::new(sessions),
+            file,
+            cipher: CredentialCipher::new(secret),
+            refresh_locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Lock serializing token refreshes of one session. Refresh tokens are
+    // single-use, so concurrent requests must not each spend it.
+    pub fn refresh_lock(&self, id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.refresh_locks.lock().unwrap();
+        // Locks nobody holds or waits for can go
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(id_hash(id)).or_default().clone()
+    }
+
+    // Start a session and return its ID for the session cookie
+    pub fn create(&self, username: &str, credentials: &Credentials, ttl: Duration) -> String {
+        let id = random_token();
+
+        let (nonce, token) = self.cipher.encrypt(credentials);
+        let now = Utc::now();
+        let session = StoredSession {
+            username: username.to_string(),
+            nonce,
+            token,
+            created_at: now.timestamp(),
+            expires_at: (now + ttl).timestamp(),
+        };
+
+        let mut sessions = self.sessions.lock().unwrap();
+        // Expired sessions are dropped whenever a new one starts
+        sessions.retain(|_, s| s.expires_at > now.timestamp());
+        sessions.insert(id_hash(&id), session);
+        self.persist(&sessions);
+        id
+    }
+
+    // Look up a live session; expired or undecryptable ones are removed
+    pub fn get(&self, id: &str) -> Option<Session> {
+        let key = id_hash(id);
+        let mut sessions = self.sessions.lock().unwrap();
+        let stored = sessions.get(&key)?;
+
+        let credentials = if stored.expires_at > Utc::now().timestamp() {
+            self.cipher.decrypt(&stored.nonce, &stored.token)
+        } else {
+            None
+        };
+        match credentials {
+            Some(credentials) => Some(Session {
+                username: stored.username.clone(),
+                credentials,
+            }),
+            None => {
+                sessions.remove(&key);
+                self.persist(&sessions);
+                None
+            }
+        }
+    }
+
+    // Replace a session's credentials after its token was refreshed
+    pub fn update(&self, id: &str, credentials: &Credentials) {
+        let (nonce, token) = self.cipher.encrypt(credentials);
+        let mut sessions = self.sessions.lock().unwrap();
+        if let Some(stored) = sessions.get_mut(&id_hash(id)) {
+            stored.nonce = nonce;
+            stored.token = token;
+            self.persist(&sessions);
+        }
+    }
+
+    // Invalidate one session (sign out)
+    pub fn remove(&self, id: &str) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.remove(&id_hash(id)).is_some() {
+            self.persist(&sessions);
+        }
+    }
+
+    // Invalidate every session of a user (sign out everywhere); returns how
+    // many were removed
+    pub fn remove_user(&self, username: &str) -> usize {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, s| s.username != username);
+        let removed = before - sessions.len();
+        if removed > 0 {
+            self.persist(&sessions);
+        }
+        removed
+    }
+
+    fn persist(&self, sessions: &HashMap<String, StoredSession>) {
+        if let Some(path) = &self.file {
+            save_json(path, sessions, "sessions");
+        }
+    }
+}
+
+// Write a store to a temporary file and rename it, so a crash never leaves a
+// half-written store behind
+pub fn save_json<T: Serialize>(path: &PathBuf, value: &T, what: &str) {
+    let tmp = path.with_extension("tmp");
+    let result = serde_json::to_vec(value)
+        .map_err(|e| e.to_string())
+        .and_then(|json| write_private(&tmp, &json).map_err(|e| e.to_string()))
+        .and_then(|_| std::fs::rename(&tmp, path).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        println!("Failed to save {} to {}: {}", what, path.display(), e);
+    }
+}
+
+// Session files are readable by the server's user only
+fn write_private(path: &PathBuf, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(data)
+}
+
+// Load a store saved by save_json; a missing file is an empty store
+pub fn load_json<T: DeserializeOwned + Default>(path: &PathBuf, what: &str) -> T {
+    match std::fs::read(path) {
+        Ok(data) => match serde_json::from_slice(&data) {
+            Ok(value) => value,
+            Err(e) => {
+                println!("Ignoring unreadable {} file {}: {}", what, path.display(), e);
+                T::default()
+            }
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+        Err(e) => {
+            println!("Failed to read {} file {}: {}", what, path.display(), e);
+            T::default()
+        }
+    }
+}
+
+// Per-token values looked up from GitHub, kept for a TTL so they aren't
+// fetched on every request. Keyed by a hash of the token, which identifies
+// the session's credential in both server-side and stateless mode.
+pub struct TokenCache<V> {
+    entries: Mutex<HashMap<String, (V, Instant)>>,
+    ttl: std::time::Duration,
+}
+
+// GitHub logins already confirmed for a token, so authenticate doesn't call
+// /user on every request
+pub type IdentityCache = TokenCache<String>;
+
+// Repositories a token can push to, for the dashboard's repository picker
+pub type RepoListCache = TokenCache<Vec<String>>;
+
+impl<V: Clone> TokenCache<V> {
+    pub fn new(ttl: std::time::Duration) -> Self {
+        TokenCache {
+            entries: Mutex::new(HashMap::new()),
+            ttl,
+        }
+    }
+
+    // The cached value, if it was fetched within the TTL
+    pub fn get(&self, token: &str) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&id_hash(token))
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < self.ttl)
+            .map(|(value, _)| value.clone())
+    }
+
+    pub fn insert(&self, token: &str, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, fetched_at)| fetched_at.elapsed() < self.ttl);
+        entries.insert(id_hash(token), (value, Instant::now()));
+    }
+
+    pub fn forget(&self, token: &str) {
+        self.entries.lock().unwrap().remove(&id_hash(token));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            token: "ghu_token".to_string(),
+            refresh_token: Some("ghr_refresh".to_string()),
+            expires_at: Some(1_700_000_000),
+        }
+    }
+
+    #[test]
+    fn cipher_round_trip() {
+        let cipher = CredentialCipher::new(b"secret");
+        let (nonce, ciphertext) = cipher.encrypt(&credentials());
+        let decrypted = cipher.decrypt(&nonce, &ciphertext).unwrap();
+        assert_eq!(decrypted.token, "ghu_token");
+        assert_eq!(decrypted.refresh_token.as_deref(), Some("ghr_refresh"));
+        assert_eq!(decrypted.expires_at, Some(1_700_000_000));
+        // A fresh nonce every time
+        assert_ne!(cipher.encrypt(&credentials()).0, nonce);
+    }
+
+    #[test]
+    fn cipher_rejects_tampering() {
+        let cipher = CredentialCipher::new(b"secret");
+        let (nonce, ciphertext) = cipher.encrypt(&credentials());
+
+        let mut bytes = general_purpose::STANDARD.decode(&ciphertext).unwrap();
+        bytes[0] ^= 1;
+        assert!(cipher.decrypt(&nonce, &general_purpose::STANDARD.encode(bytes)).is_none());
+
+        let mut other_nonce = general_purpose::STANDARD.decode(&nonce).unwrap();
+        other_nonce[0] ^= 1;
+        assert!(cipher.decrypt(&general_purpose::STANDARD.encode(other_nonce), &ciphertext).is_none());
+
+        assert!(cipher.decrypt("short", &ciphertext).is_none());
+        assert!(CredentialCipher::new(b"other secret").decrypt(&nonce, &ciphertext).is_none());
+    }
+}
@@ -4,19 +4,187 @@ use askama::Template;
 #[template(path = "index.html")]
 pub struct IndexTemplate {
     pub error: Option<String>,
+    // Missing scopes or permissions found while signing in
+    pub diagnostics: Vec<String>,
+    // Single-tenant mode: username and password instead of GitHub sign-in
+    pub local_login: bool,
+    // Signing in to another account while one is already signed in
+    pub adding_account: bool,
+    pub version: String,
+}
+
+// Shown when a GitHub account is not on the login allowlist
+#[derive(Template)]
+#[template(path = "denied.html")]
+pub struct DeniedTemplate {
+    pub username: String,
+    pub groups: Vec<String>,
+    pub lookup_error: Option<String>,
     pub version: String,
 }
 
 use crate::github::FileInfo;
+use crate::listing::ListFilter;
 
 #[derive(Template)]
 #[template(path = "dashboard.html")]
 pub struct DashboardTemplate {
     pub username: String,
-    pub repo: Option<String>,
+    pub repo: String, // "owner/name"
+    pub repos: Vec<String>,
+    pub default_repo: String,
+    // Single-tenant mode: the folder the local user is confined to ("" for
+    // the whole shared repository)
+    pub home_folder: Option<String>,
+    // GitHub Pages base URL when this repository and branch are published
+    pub pages_base: Option<String>,
+    pub branch: String,
+    pub default_branch: String,
+    pub branches: Vec<String>,
+    pub current_path: String,
+    pub breadcrumbs: Vec<(String, String)>,
+    pub folders: Vec<FileInfo>,
+    pub page: usize,
+    pub total_pages: usize,
+    pub total_entries: usize,
+    pub searching: bool,
+    // The search covered only part of the folder (GitHub truncated the tree)
+    pub incomplete: bool,
+    pub filter: ListFilter,
+    pub filter_query: String,
     pub uploaded_link: Option<String>,
     pub pages_link: Option<String>,
+    pub duplicate_of: Option<String>,
+    pub notice: Option<String>,
+    pub purge_status: Option<String>,
+    pub purge_ok: bool,
+    // Sessions are stored server-side and can be revoked everywhere
+    pub server_sessions: bool,
+    // Other accounts signed in on this browser, to switch to
+    pub other_accounts: Vec<String>,
+    pub extra_links: Vec<String>,
     pub images: Vec<FileInfo>,
     pub error: Option<String>,
     pub version: String,
 }
+
+use crate::phash::HashedImage;
+
+#[derive(Template)]
+#[template(path = "duplicates.html")]
+pub struct DuplicatesTemplate {
+    pub username: String,
+    pub repo: String,
+    pub branch: String,
+    pub raw_base: String,
+    pub groups: Vec<Vec<HashedImage>>,
+    pub threshold: u32,
+    pub status: Option<String>,
+    pub scanning: bool,
+    pub error: Option<String>,
+    pub version: String,
+}
+
+/// One row of the trash view.
+pub struct TrashItem {
+    pub path: String,
+    pub deleted_at: String,
+    pub days_left: i64,
+    pub parent_sha: String,
+    pub preview_url: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "trash.html")]
+pub struct TrashTemplate {
+    pub username: String,
+    pub repo: String,
+    pub branch: String,
+    pub retention_days: i64,
+    pub items: Vec<TrashItem>,
+    // Only the newest `commit_limit` commits of the window were searched
+    pub incomplete: bool,
+    pub commit_limit: usize,
+    pub error: Option<String>,
+    pub version: String,
+}
+
+/// One earlier (or the current) version of a file in the history view.
+pub struct FileVersion {
+    pub commit_sha: String,
+    pub message: String,
+    pub date: String,
+    // None when the commit removed the file
+    pub size: Option<u64>,
+    pub pinned_link: String,
+    pub preview_url: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "history.html")]
+pub struct HistoryTemplate {
+    pub username: String,
+    pub repo: String,
+    pub branch: String,
+    pub path: String,
+    pub folder: String,
+    pub versions: Vec<FileVersion>,
+    pub error: Option<String>,
+    pub version: String,
+}
+
+#[derive(Template)]
+#[template(path = "setup.html")]
+pub struct SetupTemplate {
+    pub username: String,
+    pub name: String,
+    pub pages_repo: String,
+    pub pages_repo_exists: bool,
+    // GitHub App mode: where to grant the app access to repositories
+    pub install_url: Option<String>,
+    pub error: Option<String>,
+    pub version: String,
+}
+
+/// One row of the upload links page.
+pub struct UploadLinkRow {
+    pub id: String,
+    pub repo: String,
+    pub folder: String,
+    pub files: String,
+    pub size: String,
+    pub types: String,
+    pub expires: String,
+    pub expired: bool,
+}
+
+#[derive(Template)]
+#[template(path = "links.html")]
+pub struct UploadLinksTemplate {
+    pub username: String,
+    pub repo: String,
+    pub folder: String,
+    // URL of the link just created; shown once since only its hash is kept
+    pub new_link: Option<String>,
+    pub links: Vec<UploadLinkRow>,
+    pub error: Option<String>,
+    // "Sign Out Everywhere" is offered, which also revokes the links
+    pub server_sessions: bool,
+    pub version: String,
+}
+
+/// Public page behind an upload link.
+#[derive(Template)]
+#[template(path = "share_upload.html")]
+pub struct ShareUploadTemplate {
+    pub owner: String,
+    pub token: String,
+    pub files_left: u32,
+    pub size_left: String,
+    pub types: String,
+    pub accept: String,
+    pub expires: String,
+    pub notice: Option<String>,
+    pub error: Option<String>,
+    pub version: String,
+}
@@ -0,0 +1,174 @@
+use chrono::Utc;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::session::{self, CredentialCipher, Credentials};
+
+/// What an upload link lets its holder do, chosen by the user who creates it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LinkLimits {
+    // Storage repository ("owner/name") and branch; an empty branch means
+    // the repository's default branch at upload time
+    pub repo: String,
+    pub branch: String,
+    pub folder: String,
+    pub max_files: u32,
+    pub max_bytes: u64,
+    // File categories (see listing::file_category) that may be uploaded;
+    // empty allows any type
+    pub allowed_types: Vec<String>,
+    pub expires_at: i64,
+}
+
+/// An upload-only share link. Whoever holds its URL can add files to one
+/// folder, within the limits, using the creator's GitHub credentials.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UploadLink {
+    // Public identifier used to revoke the link; the URL token itself is
+    // only shown once and stored hashed
+    pub id: String,
+    pub owner: String,
+    pub created_at: i64,
+    #[serde(flatten)]
+    pub limits: LinkLimits,
+    pub files_uploaded: u32,
+    pub bytes_uploaded: u64,
+    nonce: String,
+    credentials: String,
+}
+
+impl UploadLink {
+    pub fn is_expired(&self) -> bool {
+        self.limits.expires_at <= Utc::now().timestamp()
+    }
+
+    pub fn files_left(&self) -> u32 {
+        self.limits.max_files.saturating_sub(self.files_uploaded)
+    }
+
+    pub fn bytes_left(&self) -> u64 {
+        self.limits.max_bytes.saturating_sub(self.bytes_uploaded)
+    }
+}
+
+pub struct UploadLinkStore {
+    // Keyed by the SHA-256 of the URL token
+    links: Mutex<HashMap<String, UploadLink>>,
+    file: Option<PathBuf>,
+    cipher: CredentialCipher,
+}
+
+impl UploadLinkStore {
+    pub fn new(file: Option<PathBuf>, secret: &[u8]) -> Self {
+        let links = file.as_ref().map(|path| session::load_json(path, "upload link")).unwrap_or_default();
+        UploadLinkStore {
+            links: Mutex::new(links),
+            file,
+            cipher: CredentialCipher::new(secret),
+        }
+    }
+
+    // Create a link acting with `credentials` and return its URL token
+    pub fn create(&self, owner: &str, limits: LinkLimits, credentials: &Credentials) -> String {
+        let token = session::random_token();
+        let mut id = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut id);
+        let (nonce, credentials) = self.cipher.encrypt(credentials);
+        let link = UploadLink {
+            id: id.iter().map(|b| format!("{:02x}", b)).collect(),
+            owner: owner.to_string(),
+            created_at: Utc::now().timestamp(),
+            limits,
+            files_uploaded: 0,
+            bytes_uploaded: 0,
+            nonce,
+            credentials,
+        };
+
+        let mut links = self.links.lock().unwrap();
+        // Expired links are dropped whenever a new one is made
+        links.retain(|_, l| !l.is_expired());
+        links.insert(session::id_hash(&token), link);
+        self.persist(&links);
+        token
+    }
+
+    // A live link and the credentials to upload with
+    pub fn get(&self, token: &str) -> Option<(UploadLink, Credentials)> {
+        let links = self.links.lock().unwrap();
+        let link = links.get(&session::id_hash(token)).filter(|l| !l.is_expired())?;
+        let credentials = self.cipher.decrypt(&link.nonce, &link.credentials)?;
+        Some((link.clone(), credentials))
+    }
+
+    // The user's links, newest first
+    pub fn list(&self, owner: &str) -> Vec<UploadLink> {
+        let links = self.links.lock().unwrap();
+        let mut owned: Vec<UploadLink> = links.values().filter(|l| l.owner == owner).cloned().collect();
+        owned.sort_by_key(|l| std::cmp::Reverse(l.created_at));
+        owned
+    }
+
+    pub fn revoke(&self, owner: &str, id: &str) -> bool {
+        let mut links = self.links.lock().unwrap();
+        let before = links.len();
+        links.retain(|_, l| !(l.owner == owner && l.id == id));
+        let removed = links.len() < before;
+        if removed {
+            self.persist(&links);
+        }
+        removed
+    }
+
+    // Revoke every link of a user; returns how many there were
+    pub fn revoke_all(&self, owner: &str) -> usize {
+        let mut links = self.links.lock().unwrap();
+        let before = links.len();
+        links.retain(|_, l| l.owner != owner);
+        let removed = before - links.len();
+        if removed > 0 {
+            self.persist(&links);
+        }
+        removed
+    }
+
+    // Count a file against the link's quota before it is uploaded, so
+    // concurrent uploads can't overshoot it together
+    pub fn reserve(&self, token: &str, bytes: u64) -> Result<(), String> {
+        let mut links = self.links.lock().unwrap();
+        let link = links
+            .get_mut(&session::id_hash(token))
+            .filter(|l| !l.is_expired())
+            .ok_or("This upload link has expired or was revoked.")?;
+        if link.files_left() == 0 {
+            return Err("This upload link has reached its file limit.".into());
+        }
+        if bytes > link.bytes_left() {
+            let left_mb = link.bytes_left() as f64 / 1024.0 / 1024.0;
+            return Err(format!("Not enough space left on this upload link ({:.1} MB remaining).", left_mb));
+        }
+        link.files_uploaded += 1;
+        link.bytes_uploaded += bytes;
+        self.persist(&links);
+        Ok(())
+    }
+
+    // Give back a reservation whose upload failed
+    pub fn release(&self, token: &str, bytes: u64) {
+        let mut links = self.links.lock().unwrap();
+        if let Some(link) = links.get_mut(&session::id_hash(token)) {
+            link.files_uploaded = link.files_uploaded.saturating_sub(1);
+            link.bytes_uploaded = link.bytes_uploaded.saturating_sub(bytes);
+            self.persist(&links);
+        }
+    }
+
+    fn persist(&self, links: &HashMap<String, UploadLink>) {
+        if let Some(path) = &self.file {
+            session::save_json(path, links, "upload links");
+        }
+    }
+}